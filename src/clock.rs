@@ -0,0 +1,154 @@
+//! Injectable time source for span and log timestamps, see
+//! [`crate::ConfigBuilder::with_clock`].
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use opentelemetry::logs::LogRecord;
+use opentelemetry::trace::{Span as _, SpanId};
+use opentelemetry::{Context, InstrumentationScope};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::logs::{LogProcessor, SdkLogRecord};
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use opentelemetry_sdk::Resource;
+
+/// A source of timestamps.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// A clock that only moves when told to, starting at the Unix epoch by default.
+///
+/// Clones share the same time, so a test can keep one to advance the clock
+/// passed to [`crate::ConfigBuilder::with_clock`].
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Rewrites the start and end time of spans with the clock's time before
+/// handing them to `inner`.
+#[derive(Debug)]
+pub(crate) struct ClockSpanProcessor<P> {
+    clock: Arc<dyn Clock>,
+    started: Mutex<HashMap<SpanId, SystemTime>>,
+    inner: P,
+}
+
+impl<P: SpanProcessor> ClockSpanProcessor<P> {
+    pub(crate) fn new(clock: Arc<dyn Clock>, inner: P) -> Self {
+        Self {
+            clock,
+            started: Mutex::new(HashMap::new()),
+            inner,
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ClockSpanProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let span_id = span.span_context().span_id();
+        self.started.lock().unwrap().insert(span_id, self.clock.now());
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let started = self.started.lock().unwrap().remove(&span.span_context.span_id());
+        if let Some(start_time) = started {
+            span.start_time = start_time;
+        }
+        span.end_time = self.clock.now();
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Sets the timestamps of log records from the clock. Registered before the
+/// exporting processor, which sees the updated record.
+#[derive(Debug)]
+pub(crate) struct ClockLogProcessor {
+    clock: Arc<dyn Clock>,
+}
+
+impl ClockLogProcessor {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { clock }
+    }
+}
+
+impl LogProcessor for ClockLogProcessor {
+    fn emit(&self, record: &mut SdkLogRecord, _scope: &InstrumentationScope) {
+        let now = self.clock.now();
+        record.set_timestamp(now);
+        record.set_observed_timestamp(now);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::default();
+        let shared = clock.clone();
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        shared.advance(Duration::from_secs(2));
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+
+        shared.set(SystemTime::UNIX_EPOCH + Duration::from_secs(60));
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,861 @@
+//! Configuration passed to [`crate::init`].
+//!
+//! A [`Config`] describes how the service identifies itself and which
+//! exporter each signal (traces, logs and metrics) should use.
+//!
+//! # Example
+//! ```ignore
+//! let config = otex::Config::builder()
+//!     .with_service_name("billing")
+//!     .with_service_version(env!("CARGO_PKG_VERSION"))
+//!     .with_endpoint("http://collector:4318")
+//!     .with_metrics(false)
+//!     .build();
+//!
+//! otex::init(config);
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::{Key, KeyValue};
+
+use crate::clock::Clock;
+use crate::error::OtexError;
+use crate::filter::LogFilter;
+
+const DEFAULT_SERVICE_NAME: &str = "otex";
+
+/// The exporter used by a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exporter {
+    /// Export over OTLP.
+    Otlp,
+    /// Print a compact, colorized summary to stdout: spans as a tree, logs as
+    /// single lines and metrics as a table.
+    Console,
+    /// Print to stdout using the `opentelemetry_stdout` exporters.
+    #[cfg(feature = "stdout")]
+    Stdout,
+    /// Append OTLP-JSON lines to the file set with
+    /// [`ConfigBuilder::with_file_path`].
+    #[cfg(feature = "file")]
+    File,
+    /// Do not export anything.
+    None,
+}
+
+/// The OTLP transport used by a signal.
+///
+/// Each protocol requires the matching cargo feature: `grpc`, `http-proto`
+/// (enabled by default) or `http-json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// gRPC over tonic. Exporting requires a running tokio runtime.
+    Grpc,
+    /// Protobuf over HTTP.
+    HttpProtobuf,
+    /// JSON over HTTP.
+    HttpJson,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        if cfg!(feature = "http-proto") {
+            Protocol::HttpProtobuf
+        } else if cfg!(feature = "grpc") {
+            Protocol::Grpc
+        } else {
+            Protocol::HttpJson
+        }
+    }
+}
+
+/// The sampler used for new spans.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sampler {
+    /// Record and export every span.
+    AlwaysOn,
+    /// Drop every span.
+    AlwaysOff,
+    /// Sample the given fraction of traces, based on the trace ID.
+    TraceIdRatio(f64),
+    /// Follow the parent's sampling decision, using the inner sampler for root spans.
+    ParentBased(Box<Sampler>),
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::ParentBased(Box::new(Sampler::AlwaysOn))
+    }
+}
+
+/// How trace and span IDs are generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdGenerator {
+    /// Random IDs, as required for production traces.
+    #[default]
+    Random,
+    /// IDs counting up from 1, separately for traces and spans.
+    Sequential,
+    /// Pseudo-random IDs that repeat for the same seed.
+    Seeded(u64),
+}
+
+/// Tail sampling policy, see [`ConfigBuilder::with_tail_sampling`].
+///
+/// Spans are buffered per trace for [`TailSampling::with_decision_wait`]
+/// after the first one ends. The whole trace is then exported if any span has
+/// an error status, the trace took at least the latency threshold, or a span
+/// matches one of the attribute rules. Otherwise it is dropped.
+#[derive(Debug, Clone)]
+pub struct TailSampling {
+    pub(crate) decision_wait: Duration,
+    pub(crate) max_traces: usize,
+    pub(crate) keep_errors: bool,
+    pub(crate) latency_threshold: Option<Duration>,
+    pub(crate) attribute_rules: Vec<AttributeRule>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum AttributeRule {
+    Present(Key),
+    Equals(KeyValue),
+}
+
+impl Default for TailSampling {
+    fn default() -> Self {
+        Self {
+            decision_wait: Duration::from_secs(5),
+            max_traces: 10_000,
+            keep_errors: true,
+            latency_threshold: None,
+            attribute_rules: Vec::new(),
+        }
+    }
+}
+
+impl TailSampling {
+    /// Keeps traces containing an error, and nothing else until more rules are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to buffer a trace before deciding. Defaults to 5 seconds.
+    pub fn with_decision_wait(mut self, wait: Duration) -> Self {
+        self.decision_wait = wait;
+        self
+    }
+
+    /// Caps the number of buffered traces; the oldest is decided early once
+    /// the cap is exceeded. Defaults to 10 000.
+    pub fn with_max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = max_traces;
+        self
+    }
+
+    /// Keeps traces with a span whose status is an error, as set by
+    /// [`crate::new_error_event`]. Enabled by default.
+    pub fn with_errors(mut self, keep: bool) -> Self {
+        self.keep_errors = keep;
+        self
+    }
+
+    /// Keeps traces lasting at least `threshold`, from the earliest span start
+    /// to the latest span end.
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Keeps traces with a span that has the attribute `key`, whatever its value.
+    pub fn with_attribute_key(mut self, key: impl Into<Key>) -> Self {
+        self.attribute_rules.push(AttributeRule::Present(key.into()));
+        self
+    }
+
+    /// Keeps traces with a span whose attribute equals `attribute`.
+    pub fn with_attribute(mut self, attribute: KeyValue) -> Self {
+        self.attribute_rules.push(AttributeRule::Equals(attribute));
+        self
+    }
+}
+
+/// Batching of spans or log records before export.
+///
+/// Unset values fall back to the `OTEL_BSP_*` / `OTEL_BLRP_*` environment
+/// variables, then to the SDK defaults (queue of 2048, batches of 512, every
+/// 5 seconds for spans and 1 second for logs).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchConfig {
+    pub(crate) max_queue_size: Option<usize>,
+    pub(crate) max_export_batch_size: Option<usize>,
+    pub(crate) scheduled_delay: Option<Duration>,
+}
+
+impl BatchConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of buffered items; further items are dropped.
+    pub fn with_max_queue_size(mut self, max_queue_size: usize) -> Self {
+        self.max_queue_size = Some(max_queue_size);
+        self
+    }
+
+    /// Maximum number of items sent in one export request.
+    pub fn with_max_export_batch_size(mut self, max_export_batch_size: usize) -> Self {
+        self.max_export_batch_size = Some(max_export_batch_size);
+        self
+    }
+
+    /// Delay between two consecutive exports.
+    pub fn with_scheduled_delay(mut self, scheduled_delay: Duration) -> Self {
+        self.scheduled_delay = Some(scheduled_delay);
+        self
+    }
+}
+
+/// Rotation of the file written by [`Exporter::File`].
+///
+/// The active file is renamed to `<path>.1` when it would grow past the size
+/// limit or a new hour/day (UTC) starts, shifting older files to `<path>.2`,
+/// `<path>.3`, ... Nothing is rotated by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileRotation {
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) interval: Option<RotationInterval>,
+    pub(crate) max_files: Option<usize>,
+    pub(crate) gzip: bool,
+}
+
+/// How often [`FileRotation`] starts a new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationInterval {
+    Hourly,
+    Daily,
+}
+
+impl FileRotation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rotates before a batch would take the file past `max_bytes`.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Rotates at the start of every hour or day.
+    pub fn with_interval(mut self, interval: RotationInterval) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Keeps at most `max_files` rotated files, deleting the oldest.
+    /// All are kept by default.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Compresses rotated files to `<path>.N.gz`. Requires the `gzip` feature.
+    pub fn with_gzip(mut self, enabled: bool) -> Self {
+        self.gzip = enabled;
+        self
+    }
+}
+
+/// A bounded on-disk queue for OTLP batches that failed to export.
+///
+/// Failed batches are written as OTLP-JSON files to a subdirectory per signal
+/// and sent again with exponential backoff once the collector is reachable,
+/// also after a restart. When the queue is full the oldest batches are
+/// dropped. Requires the `retry-queue` feature and an HTTP protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryQueue {
+    pub(crate) dir: PathBuf,
+    pub(crate) max_bytes: u64,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+}
+
+impl RetryQueue {
+    /// Spools failed batches to `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: 64 * 1024 * 1024,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Bounds the size of each signal's queue. Defaults to 64 MiB.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Delay before the first retry, doubled after every failed attempt.
+    /// Defaults to 1 second.
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Upper bound of the retry delay. Defaults to 5 minutes.
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+}
+
+/// Settings for a single signal.
+#[derive(Debug, Clone)]
+pub(crate) struct SignalConfig {
+    pub(crate) enabled: bool,
+    pub(crate) exporter: Exporter,
+    pub(crate) protocol: Protocol,
+    pub(crate) endpoint: Option<String>,
+    /// Batching for traces and logs.
+    pub(crate) batch: BatchConfig,
+    /// Export interval for metrics.
+    pub(crate) export_interval: Option<Duration>,
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exporter: Exporter::Otlp,
+            protocol: Protocol::default(),
+            endpoint: None,
+            batch: BatchConfig::default(),
+            export_interval: None,
+        }
+    }
+}
+
+/// Request settings shared by the OTLP exporters of every signal.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OtlpConfig {
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) gzip: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) ca_certificate: Option<PathBuf>,
+    pub(crate) client_certificate: Option<PathBuf>,
+    pub(crate) client_key: Option<PathBuf>,
+}
+
+/// Configuration for [`crate::init`].
+///
+/// Use [`Config::builder`] to construct one, or [`Config::default`] to keep
+/// every signal enabled and exported over OTLP.
+///
+/// Whatever the config, [`crate::try_init`] turns off the signals disabled with
+/// `OTEX_EXPORT=false` or `OTEX_{TRACES,LOGS,METRICS}_ENABLED=false`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) service_name: String,
+    pub(crate) service_version: Option<String>,
+    pub(crate) service_namespace: Option<String>,
+    pub(crate) resource_attributes: Vec<KeyValue>,
+    pub(crate) resource_detectors: bool,
+    pub(crate) endpoint: Option<String>,
+    pub(crate) traces: SignalConfig,
+    pub(crate) logs: SignalConfig,
+    pub(crate) metrics: SignalConfig,
+    pub(crate) sampler: Sampler,
+    pub(crate) span_samplers: Vec<(String, Sampler)>,
+    pub(crate) tail_sampling: Option<TailSampling>,
+    pub(crate) id_generator: IdGenerator,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) log_filter: Option<LogFilter>,
+    pub(crate) otlp: OtlpConfig,
+    pub(crate) retry_queue: Option<RetryQueue>,
+    pub(crate) file_path: Option<PathBuf>,
+    pub(crate) file_rotation: FileRotation,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            service_name: DEFAULT_SERVICE_NAME.to_string(),
+            service_version: None,
+            service_namespace: None,
+            resource_attributes: Vec::new(),
+            resource_detectors: true,
+            endpoint: None,
+            traces: SignalConfig::default(),
+            logs: SignalConfig::default(),
+            metrics: SignalConfig::default(),
+            sampler: Sampler::default(),
+            span_samplers: Vec::new(),
+            tail_sampling: None,
+            id_generator: IdGenerator::default(),
+            clock: None,
+            log_filter: None,
+            otlp: OtlpConfig::default(),
+            retry_queue: None,
+            file_path: None,
+            file_rotation: FileRotation::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Builds a config from the `OTEL_*` environment variables.
+    /// See [`ConfigBuilder::from_env`].
+    pub fn from_env() -> Config {
+        ConfigBuilder::from_env().build()
+    }
+
+    pub fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
+    pub fn service_version(&self) -> Option<&str> {
+        self.service_version.as_deref()
+    }
+
+    pub fn service_namespace(&self) -> Option<&str> {
+        self.service_namespace.as_deref()
+    }
+
+    /// Checks that the service name is set, every endpoint is an absolute URI,
+    /// the OTLP headers are valid and file exporters have a path.
+    pub(crate) fn validate(&self) -> Result<(), OtexError> {
+        if self.service_name.trim().is_empty() {
+            return Err(OtexError::InvalidConfig("service name is empty".to_string()));
+        }
+
+        let endpoints = [
+            &self.endpoint,
+            &self.traces.endpoint,
+            &self.logs.endpoint,
+            &self.metrics.endpoint,
+        ];
+        for endpoint in endpoints.into_iter().flatten() {
+            let valid = endpoint
+                .parse::<http::Uri>()
+                .is_ok_and(|uri| uri.scheme().is_some() && uri.host().is_some());
+            if !valid {
+                return Err(OtexError::InvalidConfig(format!("invalid endpoint {:?}", endpoint)));
+            }
+        }
+
+        for (name, value) in &self.otlp.headers {
+            let valid = http::HeaderName::from_bytes(name.as_bytes()).is_ok()
+                && http::HeaderValue::from_str(value).is_ok();
+            if !valid {
+                return Err(OtexError::InvalidConfig(format!("invalid header {:?}", name)));
+            }
+        }
+
+        if self.otlp.client_certificate.is_some() != self.otlp.client_key.is_some() {
+            return Err(OtexError::InvalidConfig(
+                "client certificate and key must be set together".to_string(),
+            ));
+        }
+
+        #[cfg(feature = "file")]
+        if self.file_path.is_none() {
+            let signals = [&self.traces, &self.logs, &self.metrics];
+            if signals.iter().any(|signal| signal.enabled && signal.exporter == Exporter::File) {
+                return Err(OtexError::InvalidConfig(
+                    "file exporter requires a file path".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the OTLP endpoint for a signal.
+    ///
+    /// A signal specific endpoint is used verbatim, otherwise `path` is
+    /// appended to the shared endpoint for HTTP. Returns `None` to let the
+    /// exporter fall back to its own defaults.
+    #[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+    pub(crate) fn signal_endpoint(&self, signal: &SignalConfig, path: &str) -> Option<String> {
+        let path = if signal.protocol == Protocol::Grpc { "" } else { path };
+        signal.endpoint.clone().or_else(|| {
+            self.endpoint
+                .as_ref()
+                .map(|endpoint| format!("{}{}", endpoint.trim_end_matches('/'), path))
+        })
+    }
+}
+
+/// Builder for [`Config`].
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    pub(crate) config: Config,
+}
+
+impl ConfigBuilder {
+    /// Starts from the standard OpenTelemetry environment variables, so that
+    /// settings applied afterwards on the builder take precedence.
+    ///
+    /// Understands `OTEL_SERVICE_NAME`, `OTEL_RESOURCE_ATTRIBUTES`,
+    /// `OTEL_{TRACES,LOGS,METRICS}_EXPORTER` (`otlp`, `console`, `stdout`, `file`, `none`),
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_{TRACES,LOGS,METRICS}_ENDPOINT`,
+    /// `OTEL_EXPORTER_OTLP_PROTOCOL`, `OTEL_EXPORTER_OTLP_{TRACES,LOGS,METRICS}_PROTOCOL`,
+    /// `OTEL_EXPORTER_OTLP_HEADERS`, `OTEL_EXPORTER_OTLP_COMPRESSION`, `OTEL_EXPORTER_OTLP_TIMEOUT`,
+    /// `OTEL_EXPORTER_OTLP_CERTIFICATE`, `OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE`,
+    /// `OTEL_EXPORTER_OTLP_CLIENT_KEY`,
+    /// `OTEL_BSP_{SCHEDULE_DELAY,MAX_QUEUE_SIZE,MAX_EXPORT_BATCH_SIZE}`, the matching
+    /// `OTEL_BLRP_*` variables, `OTEL_METRIC_EXPORT_INTERVAL`,
+    /// `OTEL_TRACES_SAMPLER`, `OTEL_TRACES_SAMPLER_ARG`, `OTEX_TRACES_SAMPLER_OVERRIDES`
+    /// (`name=sampler[:arg],...`) and `OTEL_SDK_DISABLED`,
+    /// as well as `OTEX_{TRACES,LOGS,METRICS}_ENABLED`, `OTEX_FILE_PATH`,
+    /// `OTEX_FILE_{MAX_BYTES,ROTATION,MAX_FILES,COMPRESSION}`, `OTEX_RETRY_QUEUE_{DIR,MAX_BYTES}`,
+    /// `OTEX_ID_GENERATOR` (`random`, `sequential`, `seeded:<seed>`), `OTEX_LOG`
+    /// (see [`LogFilter`]) and the legacy `OTEX_EXPORT=false`.
+    pub fn from_env() -> Self {
+        crate::env::load(Self::default())
+    }
+
+    /// Sets the service name, which is also used as the instrumentation scope.
+    pub fn with_service_name(mut self, name: impl Into<String>) -> Self {
+        self.config.service_name = name.into();
+        self
+    }
+
+    pub fn with_service_version(mut self, version: impl Into<String>) -> Self {
+        self.config.service_version = Some(version.into());
+        self
+    }
+
+    pub fn with_service_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.config.service_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Adds an attribute to the resource shared by all signals.
+    pub fn with_resource_attribute(mut self, attribute: KeyValue) -> Self {
+        self.config.resource_attributes.push(attribute);
+        self
+    }
+
+    pub fn with_resource_attributes(
+        mut self,
+        attributes: impl IntoIterator<Item = KeyValue>,
+    ) -> Self {
+        self.config.resource_attributes.extend(attributes);
+        self
+    }
+
+    /// Enables the built-in host, OS, process, container and Kubernetes
+    /// resource detectors. Enabled by default.
+    pub fn with_resource_detectors(mut self, enabled: bool) -> Self {
+        self.config.resource_detectors = enabled;
+        self
+    }
+
+    /// Enables or disables traces. No tracer provider is built for a disabled
+    /// signal: spans become no-ops that still propagate the parent context.
+    pub fn with_traces(mut self, enabled: bool) -> Self {
+        self.config.traces.enabled = enabled;
+        self
+    }
+
+    /// Enables or disables logs. Log records are dropped when disabled.
+    pub fn with_logs(mut self, enabled: bool) -> Self {
+        self.config.logs.enabled = enabled;
+        self
+    }
+
+    /// Enables or disables metrics. [`crate::meter`] then returns a meter
+    /// whose instruments record nothing.
+    pub fn with_metrics(mut self, enabled: bool) -> Self {
+        self.config.metrics.enabled = enabled;
+        self
+    }
+
+    /// Sets the exporter for every signal.
+    pub fn with_exporter(mut self, exporter: Exporter) -> Self {
+        self.config.traces.exporter = exporter;
+        self.config.logs.exporter = exporter;
+        self.config.metrics.exporter = exporter;
+        self
+    }
+
+    pub fn with_traces_exporter(mut self, exporter: Exporter) -> Self {
+        self.config.traces.exporter = exporter;
+        self
+    }
+
+    pub fn with_logs_exporter(mut self, exporter: Exporter) -> Self {
+        self.config.logs.exporter = exporter;
+        self
+    }
+
+    pub fn with_metrics_exporter(mut self, exporter: Exporter) -> Self {
+        self.config.metrics.exporter = exporter;
+        self
+    }
+
+    /// Sets the OTLP protocol for every signal.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.config.traces.protocol = protocol;
+        self.config.logs.protocol = protocol;
+        self.config.metrics.protocol = protocol;
+        self
+    }
+
+    pub fn with_traces_protocol(mut self, protocol: Protocol) -> Self {
+        self.config.traces.protocol = protocol;
+        self
+    }
+
+    pub fn with_logs_protocol(mut self, protocol: Protocol) -> Self {
+        self.config.logs.protocol = protocol;
+        self
+    }
+
+    pub fn with_metrics_protocol(mut self, protocol: Protocol) -> Self {
+        self.config.metrics.protocol = protocol;
+        self
+    }
+
+    /// Sets the base OTLP endpoint shared by all signals
+    /// (e.g. `http://collector:4318`). For HTTP the signal path is appended.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets the full OTLP endpoint for traces, overriding [`Self::with_endpoint`].
+    pub fn with_traces_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.traces.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets the full OTLP endpoint for logs, overriding [`Self::with_endpoint`].
+    pub fn with_logs_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.logs.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets the full OTLP endpoint for metrics, overriding [`Self::with_endpoint`].
+    pub fn with_metrics_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.metrics.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Adds a header sent with every OTLP export request, e.g. an API key.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.otlp.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_headers<K, V>(mut self, headers: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let headers = headers.into_iter().map(|(name, value)| (name.into(), value.into()));
+        self.config.otlp.headers.extend(headers);
+        self
+    }
+
+    /// Compresses OTLP export requests with gzip. Requires the `gzip` feature,
+    /// or `grpc-gzip` for gRPC.
+    pub fn with_gzip(mut self, enabled: bool) -> Self {
+        self.config.otlp.gzip = enabled;
+        self
+    }
+
+    /// Sets the timeout of a single OTLP export request. Defaults to 10 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.config.otlp.timeout = Some(timeout);
+        self
+    }
+
+    /// Trusts the PEM encoded CA bundle at `path` in addition to the system
+    /// roots. Requires the `tls` feature, or `grpc-tls` for gRPC.
+    pub fn with_ca_certificate(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.otlp.ca_certificate = Some(path.into());
+        self
+    }
+
+    /// Authenticates with the PEM encoded client certificate and private key
+    /// (mutual TLS). Requires the `tls` feature, or `grpc-tls` for gRPC.
+    pub fn with_client_certificate(
+        mut self,
+        certificate: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> Self {
+        self.config.otlp.client_certificate = Some(certificate.into());
+        self.config.otlp.client_key = Some(key.into());
+        self
+    }
+
+    /// Spools OTLP batches that failed to export to disk and retries them.
+    /// Requires the `retry-queue` feature.
+    pub fn with_retry_queue(mut self, queue: RetryQueue) -> Self {
+        self.config.retry_queue = Some(queue);
+        self
+    }
+
+    /// Sets the JSON Lines file written by [`Exporter::File`]. Signals share
+    /// the file, each export batch becoming one line.
+    pub fn with_file_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.file_path = Some(path.into());
+        self
+    }
+
+    /// Rotates the file written by [`Exporter::File`].
+    pub fn with_file_rotation(mut self, rotation: FileRotation) -> Self {
+        self.config.file_rotation = rotation;
+        self
+    }
+
+    pub fn with_traces_batch(mut self, batch: BatchConfig) -> Self {
+        self.config.traces.batch = batch;
+        self
+    }
+
+    pub fn with_logs_batch(mut self, batch: BatchConfig) -> Self {
+        self.config.logs.batch = batch;
+        self
+    }
+
+    /// Sets how often metrics are exported. Defaults to `OTEL_METRIC_EXPORT_INTERVAL`
+    /// or 60 seconds.
+    pub fn with_metrics_export_interval(mut self, interval: Duration) -> Self {
+        self.config.metrics.export_interval = Some(interval);
+        self
+    }
+
+    /// Sets the sampler for new spans. Defaults to parent based, always on.
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.config.sampler = sampler;
+        self
+    }
+
+    /// Uses `sampler` instead of the default one for spans named `pattern`,
+    /// e.g. to drop health checks. A trailing `*` matches any span name
+    /// starting with the rest of the pattern. Later overrides take precedence.
+    pub fn with_span_sampler(mut self, pattern: impl Into<String>, sampler: Sampler) -> Self {
+        self.config.span_samplers.push((pattern.into(), sampler));
+        self
+    }
+
+    /// Buffers finished spans per trace and only exports the traces selected
+    /// by `policy`. Applies after the head sampler of [`Self::with_sampler`].
+    pub fn with_tail_sampling(mut self, policy: TailSampling) -> Self {
+        self.config.tail_sampling = Some(policy);
+        self
+    }
+
+    /// Sets how trace and span IDs are generated, e.g. [`IdGenerator::Sequential`]
+    /// for reproducible test output.
+    pub fn with_id_generator(mut self, id_generator: IdGenerator) -> Self {
+        self.config.id_generator = id_generator;
+        self
+    }
+
+    /// Takes the timestamps of spans, span events and log records from
+    /// `clock` instead of the system time, see [`crate::ManualClock`].
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.config.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Drops log records from the otex log macros that `filter` rejects,
+    /// before they are built.
+    pub fn with_log_filter(mut self, filter: LogFilter) -> Self {
+        self.config.log_filter = Some(filter);
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Exporter};
+
+    #[test]
+    fn builder_defaults() {
+        let config = Config::builder().build();
+
+        assert_eq!(config.service_name(), "otex");
+        assert!(config.traces.enabled && config.logs.enabled && config.metrics.enabled);
+        assert_eq!(config.traces.exporter, Exporter::Otlp);
+    }
+
+    #[test]
+    #[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+    fn signal_endpoint() {
+        let config = Config::builder()
+            .with_service_name("billing")
+            .with_protocol(super::Protocol::HttpProtobuf)
+            .with_endpoint("http://collector:4318/")
+            .with_logs_endpoint("http://logs:4318/custom")
+            .build();
+
+        assert_eq!(config.service_name(), "billing");
+        assert_eq!(
+            config.signal_endpoint(&config.traces, "/v1/traces").as_deref(),
+            Some("http://collector:4318/v1/traces")
+        );
+        assert_eq!(
+            config.signal_endpoint(&config.logs, "/v1/logs").as_deref(),
+            Some("http://logs:4318/custom")
+        );
+
+        let default = Config::default();
+        assert_eq!(default.signal_endpoint(&default.metrics, "/v1/metrics"), None);
+
+        let grpc = Config::builder()
+            .with_endpoint("http://collector:4317")
+            .with_traces_protocol(super::Protocol::Grpc)
+            .build();
+        assert_eq!(
+            grpc.signal_endpoint(&grpc.traces, "/v1/traces").as_deref(),
+            Some("http://collector:4317")
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Config::default().validate().is_ok());
+        assert!(Config::builder().with_service_name(" ").build().validate().is_err());
+        assert!(Config::builder().with_endpoint("collector").build().validate().is_err());
+        assert!(
+            Config::builder()
+                .with_traces_endpoint("http://collector:4318/v1/traces")
+                .build()
+                .validate()
+                .is_ok()
+        );
+
+        assert!(Config::builder().with_header("api key", "x").build().validate().is_err());
+
+        let mut config = Config::builder().with_client_certificate("client.pem", "client.key").build();
+        assert!(config.validate().is_ok());
+        config.otlp.client_key = None;
+        assert!(config.validate().is_err());
+
+        #[cfg(feature = "file")]
+        {
+            let config = Config::builder().with_traces_exporter(Exporter::File);
+            assert!(config.build().validate().is_err());
+            let config = Config::builder().with_exporter(Exporter::File).with_file_path("otex.jsonl");
+            assert!(config.build().validate().is_ok());
+        }
+    }
+}
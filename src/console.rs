@@ -0,0 +1,431 @@
+//! Compact, colorized console exporters for local development.
+//!
+//! Spans are printed as an indented tree per trace with their durations, log
+//! records as single lines with severity and trace context, and metrics as a
+//! table on every collection. Colors are used when stdout is a terminal and
+//! `NO_COLOR` is not set.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Write as _};
+use std::io::{IsTerminal, Write as _};
+use std::time::{Duration, SystemTime};
+
+use opentelemetry::logs::AnyValue;
+use opentelemetry::trace::{SpanId, Status};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::logs::{LogBatch, SdkLogRecord};
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+use opentelemetry_sdk::trace::SpanData;
+
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const BLUE: &str = "34";
+const MAGENTA: &str = "35";
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn detect() -> Self {
+        Self {
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn paint(self, code: &str, text: impl Display) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+fn print(output: String) -> OTelSdkResult {
+    std::io::stdout()
+        .lock()
+        .write_all(output.as_bytes())
+        .map_err(|e| OTelSdkError::InternalFailure(format!("failed to write to stdout: {}", e)))
+}
+
+#[derive(Debug)]
+pub(crate) struct ConsoleSpanExporter {
+    style: Style,
+}
+
+impl ConsoleSpanExporter {
+    pub(crate) fn new() -> Self {
+        Self { style: Style::detect() }
+    }
+}
+
+impl opentelemetry_sdk::trace::SpanExporter for ConsoleSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        print(format_spans(&batch, self.style))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ConsoleLogExporter {
+    style: Style,
+}
+
+impl ConsoleLogExporter {
+    pub(crate) fn new() -> Self {
+        Self { style: Style::detect() }
+    }
+}
+
+impl opentelemetry_sdk::logs::LogExporter for ConsoleLogExporter {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        let output: String = batch
+            .iter()
+            .map(|(record, scope)| format_log(record, scope.name(), self.style))
+            .collect();
+        print(output)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ConsoleMetricExporter {
+    style: Style,
+}
+
+impl ConsoleMetricExporter {
+    pub(crate) fn new() -> Self {
+        Self { style: Style::detect() }
+    }
+}
+
+impl opentelemetry_sdk::metrics::exporter::PushMetricExporter for ConsoleMetricExporter {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        print(format_metrics(metrics, self.style))
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn temporality(&self) -> Temporality {
+        Temporality::Cumulative
+    }
+}
+
+/// Prints every span whose parent is not in the batch as the root of a tree.
+fn format_spans(spans: &[SpanData], style: Style) -> String {
+    let ids: HashSet<SpanId> = spans.iter().map(|span| span.span_context.span_id()).collect();
+    let mut roots: Vec<&SpanData> = spans
+        .iter()
+        .filter(|span| !ids.contains(&span.parent_span_id))
+        .collect();
+    roots.sort_by_key(|span| span.start_time);
+
+    let mut output = String::new();
+    for root in roots {
+        format_span(&mut output, spans, root, 0, style);
+    }
+    output
+}
+
+fn format_span(output: &mut String, spans: &[SpanData], span: &SpanData, depth: usize, style: Style) {
+    let indent = "  ".repeat(depth);
+    let duration = span.end_time.duration_since(span.start_time).unwrap_or_default();
+    let name = match &span.status {
+        Status::Error { .. } => style.paint(RED, &span.name),
+        _ => style.paint(BOLD, &span.name),
+    };
+    let _ = write!(output, "{}{} {}", indent, name, style.paint(YELLOW, format_duration(duration)));
+    if let Status::Error { description } = &span.status
+        && !description.is_empty()
+    {
+        let _ = write!(output, " {}", style.paint(RED, description));
+    }
+    output.push_str(&format_attributes(&span.attributes, style));
+    if depth == 0 {
+        let trace_id = format!("trace_id={}", span.span_context.trace_id());
+        let _ = write!(output, " {}", style.paint(DIM, trace_id));
+    }
+    output.push('\n');
+
+    for event in span.events.iter() {
+        let _ = writeln!(
+            output,
+            "{}  {} {}{}",
+            indent,
+            style.paint(DIM, "•"),
+            event.name,
+            format_attributes(&event.attributes, style)
+        );
+    }
+
+    let mut children: Vec<&SpanData> = spans
+        .iter()
+        .filter(|child| child.parent_span_id == span.span_context.span_id())
+        .collect();
+    children.sort_by_key(|child| child.start_time);
+    for child in children {
+        format_span(output, spans, child, depth + 1, style);
+    }
+}
+
+fn format_log(record: &SdkLogRecord, scope: &str, style: Style) -> String {
+    let mut output = String::new();
+    if let Some(time) = record.timestamp().or(record.observed_timestamp()) {
+        let _ = write!(output, "{} ", style.paint(DIM, format_time(time)));
+    }
+
+    let severity = record.severity_number();
+    let text = record
+        .severity_text()
+        .or(severity.map(|severity| severity.name()))
+        .unwrap_or("LOG");
+    let color = match severity.map(|severity| severity as u8).unwrap_or(0) {
+        0..=4 => DIM,
+        5..=8 => BLUE,
+        9..=12 => GREEN,
+        13..=16 => YELLOW,
+        17..=20 => RED,
+        _ => MAGENTA,
+    };
+    let _ = write!(output, "{} ", style.paint(color, format!("{:<5}", text)));
+
+    let target = record.target().map(|target| target.as_ref()).unwrap_or(scope);
+    let _ = write!(output, "{}", style.paint(DIM, target));
+    if let Some(name) = record.event_name() {
+        let _ = write!(output, " {}", style.paint(BOLD, name));
+    }
+    if let Some(body) = record.body() {
+        let _ = write!(output, " {}", format_any_value(body));
+    }
+
+    for (key, value) in record.attributes_iter() {
+        let _ = write!(output, " {}", style.paint(DIM, format!("{}={}", key, format_any_value(value))));
+    }
+    if let Some(context) = record.trace_context() {
+        let ids = format!("trace_id={} span_id={}", context.trace_id, context.span_id);
+        let _ = write!(output, " {}", style.paint(DIM, ids));
+    }
+    output.push('\n');
+    output
+}
+
+/// Prints one row per data point: name, kind, value and attributes.
+fn format_metrics(metrics: &ResourceMetrics, style: Style) -> String {
+    let mut rows = Vec::new();
+    for scope in metrics.scope_metrics() {
+        for metric in scope.metrics() {
+            let name = match metric.unit() {
+                "" => metric.name().to_string(),
+                unit => format!("{} ({})", metric.name(), unit),
+            };
+            match metric.data() {
+                AggregatedMetrics::F64(data) => metric_rows(&mut rows, &name, data),
+                AggregatedMetrics::U64(data) => metric_rows(&mut rows, &name, data),
+                AggregatedMetrics::I64(data) => metric_rows(&mut rows, &name, data),
+            }
+        }
+    }
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let header = ["METRIC".to_string(), "KIND".to_string(), "VALUE".to_string(), String::new()];
+    let mut widths = [0; 3];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for (i, row) in std::iter::once(&header).chain(&rows).enumerate() {
+        let line = format!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+        let line = if i == 0 { style.paint(BOLD, line.trim_end()) } else { line.trim_end().to_string() };
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+fn metric_rows<T: Display + Copy>(rows: &mut Vec<[String; 4]>, name: &str, data: &MetricData<T>) {
+    let plain = Style { color: false };
+    let mut push = |kind: &str, value: String, attributes: Vec<KeyValue>| {
+        let attributes = format_attributes(&attributes, plain).trim_start().to_string();
+        rows.push([name.to_string(), kind.to_string(), value, attributes]);
+    };
+    match data {
+        MetricData::Gauge(gauge) => {
+            for point in gauge.data_points() {
+                push("gauge", point.value().to_string(), point.attributes().cloned().collect());
+            }
+        }
+        MetricData::Sum(sum) => {
+            let kind = if sum.is_monotonic() { "counter" } else { "updown" };
+            for point in sum.data_points() {
+                push(kind, point.value().to_string(), point.attributes().cloned().collect());
+            }
+        }
+        MetricData::Histogram(histogram) => {
+            for point in histogram.data_points() {
+                let mut value = format!("count={} sum={}", point.count(), point.sum());
+                if let (Some(min), Some(max)) = (point.min(), point.max()) {
+                    let _ = write!(value, " min={} max={}", min, max);
+                }
+                push("histogram", value, point.attributes().cloned().collect());
+            }
+        }
+        MetricData::ExponentialHistogram(histogram) => {
+            for point in histogram.data_points() {
+                let value = format!("count={} sum={}", point.count(), point.sum());
+                push("histogram", value, point.attributes().cloned().collect());
+            }
+        }
+    }
+}
+
+fn format_attributes(attributes: &[KeyValue], style: Style) -> String {
+    attributes
+        .iter()
+        .map(|kv| format!(" {}", style.paint(DIM, format!("{}={}", kv.key, kv.value))))
+        .collect()
+}
+
+fn format_any_value(value: &AnyValue) -> String {
+    match value {
+        AnyValue::Int(i) => i.to_string(),
+        AnyValue::Double(d) => d.to_string(),
+        AnyValue::String(s) => s.to_string(),
+        AnyValue::Boolean(b) => b.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{}µs", duration.as_micros())
+    } else if duration < Duration::from_secs(1) {
+        format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+/// Formats the UTC time of day as `HH:MM:SS.mmm`.
+fn format_time(time: SystemTime) -> String {
+    let elapsed = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs() % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use opentelemetry::logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity};
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry::KeyValue;
+
+    use super::{Style, format_duration, format_log, format_metrics, format_spans, format_time};
+
+    const PLAIN: Style = Style { color: false };
+
+    #[test]
+    fn spans_as_tree() {
+        let exporter = opentelemetry_sdk::trace::InMemorySpanExporter::default();
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        tracer.in_span("hello", |cx| {
+            tracer.in_span("world", |cx| {
+                cx.span().set_attribute(KeyValue::new("child_attr", 123));
+                cx.span().add_event("retry", vec![KeyValue::new("attempt", 2)]);
+            });
+            cx.span().set_status(opentelemetry::trace::Status::error("boom"));
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let output = format_spans(&spans, PLAIN);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", output);
+        assert!(lines[0].starts_with("hello ") && lines[0].contains(" boom trace_id="));
+        assert!(lines[1].starts_with("  world ") && lines[1].ends_with(" child_attr=123"));
+        assert_eq!(lines[2], "    • retry attempt=2");
+    }
+
+    #[test]
+    fn log_line() {
+        let exporter = opentelemetry_sdk::logs::InMemoryLogExporter::default();
+        let provider = opentelemetry_sdk::logs::SdkLoggerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let logger = provider.logger("billing");
+        let mut record = logger.create_log_record();
+        record.set_timestamp(SystemTime::UNIX_EPOCH + Duration::from_millis(3_723_004));
+        record.set_severity_number(Severity::Warn);
+        record.set_severity_text(Severity::Warn.name());
+        record.set_body(AnyValue::from("disk almost full"));
+        record.add_attribute("free", 42);
+        logger.emit(record);
+
+        let logs = exporter.get_emitted_logs().unwrap();
+        let line = format_log(&logs[0].record, logs[0].instrumentation.name(), PLAIN);
+        assert_eq!(line, "01:02:03.004 WARN  billing disk almost full free=42\n");
+    }
+
+    #[test]
+    fn metrics_table() {
+        let exporter = opentelemetry_sdk::metrics::InMemoryMetricExporter::default();
+        let provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+            .with_periodic_exporter(exporter.clone())
+            .build();
+        let meter = provider.meter("test");
+        meter.u64_counter("requests").build().add(2, &[KeyValue::new("route", "/")]);
+        meter.i64_up_down_counter("connections").build().add(-1, &[]);
+
+        provider.force_flush().unwrap();
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let output = format_metrics(&metrics[0], PLAIN);
+        assert_eq!(
+            output,
+            "METRIC       KIND     VALUE\n\
+             requests     counter  2      route=/\n\
+             connections  updown   -1\n"
+        );
+    }
+
+    #[test]
+    fn durations_and_times() {
+        assert_eq!(format_duration(Duration::from_micros(250)), "250µs");
+        assert_eq!(format_duration(Duration::from_micros(12_345)), "12.3ms");
+        assert_eq!(format_duration(Duration::from_millis(2_500)), "2.50s");
+        assert_eq!(format_time(SystemTime::UNIX_EPOCH + Duration::from_secs(86_399)), "23:59:59.000");
+    }
+}
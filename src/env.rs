@@ -0,0 +1,559 @@
+//! Loading [`crate::Config`] values from the standard `OTEL_*` environment variables.
+//!
+//! Invalid values are logged and ignored, leaving the previous setting in
+//! place, as recommended by the OpenTelemetry specification.
+
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+
+use crate::config::{
+    Config, ConfigBuilder, Exporter, IdGenerator, Protocol, RetryQueue, RotationInterval, Sampler,
+};
+
+/// Applies the environment variables to `builder`.
+pub(crate) fn load(builder: ConfigBuilder) -> ConfigBuilder {
+    load_from(builder, |name| std::env::var(name).ok())
+}
+
+fn load_from(mut builder: ConfigBuilder, var: impl Fn(&str) -> Option<String>) -> ConfigBuilder {
+    let var = |name: &str| {
+        var(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let config = &mut builder.config;
+
+    // Resource
+    if let Some(attributes) = var("OTEL_RESOURCE_ATTRIBUTES") {
+        for (key, value) in parse_key_values(&attributes) {
+            match key.as_str() {
+                "service.name" => config.service_name = value,
+                "service.version" => config.service_version = Some(value),
+                "service.namespace" => config.service_namespace = Some(value),
+                _ => config.resource_attributes.push(KeyValue::new(key, value)),
+            }
+        }
+    }
+    if let Some(service_name) = var("OTEL_SERVICE_NAME") {
+        config.service_name = service_name;
+    }
+
+    // Exporters
+    if let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        config.endpoint = Some(endpoint);
+    }
+    let protocol = var("OTEL_EXPORTER_OTLP_PROTOCOL");
+    let signals = [
+        ("TRACES", &mut config.traces),
+        ("LOGS", &mut config.logs),
+        ("METRICS", &mut config.metrics),
+    ];
+    for (signal, signal_config) in signals {
+        let name = format!("OTEX_{}_ENABLED", signal);
+        if let Some(value) = var(&name) {
+            match parse_bool(&value) {
+                Some(enabled) => signal_config.enabled = enabled,
+                None => log::warn!("ignoring invalid {} {:?}", name, value),
+            }
+        }
+        if let Some(exporter) = var(&format!("OTEL_{}_EXPORTER", signal)) {
+            match parse_exporter(&exporter) {
+                Some(exporter) => signal_config.exporter = exporter,
+                None => log::warn!("ignoring unsupported OTEL_{}_EXPORTER {:?}", signal, exporter),
+            }
+        }
+        if let Some(endpoint) = var(&format!("OTEL_EXPORTER_OTLP_{}_ENDPOINT", signal)) {
+            signal_config.endpoint = Some(endpoint);
+        }
+        let name = format!("OTEL_EXPORTER_OTLP_{}_PROTOCOL", signal);
+        if let Some(value) = var(&name).or_else(|| protocol.clone()) {
+            match parse_protocol(&value) {
+                Some(protocol) => signal_config.protocol = protocol,
+                None => log::warn!("ignoring unsupported OTLP protocol {:?}", value),
+            }
+        }
+    }
+
+    // OTLP requests
+    if let Some(headers) = var("OTEL_EXPORTER_OTLP_HEADERS") {
+        config.otlp.headers.extend(parse_key_values(&headers));
+    }
+    if let Some(compression) = var("OTEL_EXPORTER_OTLP_COMPRESSION") {
+        match compression.as_str() {
+            "gzip" => config.otlp.gzip = true,
+            "none" => config.otlp.gzip = false,
+            _ => log::warn!("ignoring unsupported OTEL_EXPORTER_OTLP_COMPRESSION {:?}", compression),
+        }
+    }
+    if let Some(timeout) = millis(&var, "OTEL_EXPORTER_OTLP_TIMEOUT") {
+        config.otlp.timeout = Some(timeout);
+    }
+    if let Some(path) = var("OTEL_EXPORTER_OTLP_CERTIFICATE") {
+        config.otlp.ca_certificate = Some(path.into());
+    }
+    if let Some(path) = var("OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE") {
+        config.otlp.client_certificate = Some(path.into());
+    }
+    if let Some(path) = var("OTEL_EXPORTER_OTLP_CLIENT_KEY") {
+        config.otlp.client_key = Some(path.into());
+    }
+    if let Some(dir) = var("OTEX_RETRY_QUEUE_DIR") {
+        config.retry_queue = Some(RetryQueue::new(dir));
+    }
+    if let Some(size) = number(&var, "OTEX_RETRY_QUEUE_MAX_BYTES") {
+        match &mut config.retry_queue {
+            Some(queue) => queue.max_bytes = size,
+            None => log::warn!("ignoring OTEX_RETRY_QUEUE_MAX_BYTES without OTEX_RETRY_QUEUE_DIR"),
+        }
+    }
+
+    // File exporter
+    if let Some(path) = var("OTEX_FILE_PATH") {
+        config.file_path = Some(path.into());
+    }
+    if let Some(size) = number(&var, "OTEX_FILE_MAX_BYTES") {
+        config.file_rotation.max_bytes = Some(size);
+    }
+    if let Some(rotation) = var("OTEX_FILE_ROTATION") {
+        match rotation.to_lowercase().as_str() {
+            "hourly" => config.file_rotation.interval = Some(RotationInterval::Hourly),
+            "daily" => config.file_rotation.interval = Some(RotationInterval::Daily),
+            "none" => config.file_rotation.interval = None,
+            _ => log::warn!("ignoring unsupported OTEX_FILE_ROTATION {:?}", rotation),
+        }
+    }
+    if let Some(count) = number(&var, "OTEX_FILE_MAX_FILES") {
+        config.file_rotation.max_files = Some(count);
+    }
+    if let Some(compression) = var("OTEX_FILE_COMPRESSION") {
+        match compression.as_str() {
+            "gzip" => config.file_rotation.gzip = true,
+            "none" => config.file_rotation.gzip = false,
+            _ => log::warn!("ignoring unsupported OTEX_FILE_COMPRESSION {:?}", compression),
+        }
+    }
+
+    // Batching
+    for (prefix, batch) in [("OTEL_BSP", &mut config.traces.batch), ("OTEL_BLRP", &mut config.logs.batch)] {
+        if let Some(delay) = millis(&var, &format!("{}_SCHEDULE_DELAY", prefix)) {
+            batch.scheduled_delay = Some(delay);
+        }
+        if let Some(size) = number(&var, &format!("{}_MAX_QUEUE_SIZE", prefix)) {
+            batch.max_queue_size = Some(size);
+        }
+        if let Some(size) = number(&var, &format!("{}_MAX_EXPORT_BATCH_SIZE", prefix)) {
+            batch.max_export_batch_size = Some(size);
+        }
+    }
+    if let Some(interval) = millis(&var, "OTEL_METRIC_EXPORT_INTERVAL") {
+        config.metrics.export_interval = Some(interval);
+    }
+
+    switches_from(config, &var);
+
+    // Sampler
+    if let Some(sampler) = var("OTEL_TRACES_SAMPLER") {
+        let argument = var("OTEL_TRACES_SAMPLER_ARG");
+        match parse_sampler(&sampler, argument.as_deref()) {
+            Some(sampler) => config.sampler = sampler,
+            None => log::warn!("ignoring unsupported OTEL_TRACES_SAMPLER {:?}", sampler),
+        }
+    }
+    // `name=sampler[:arg],...`, e.g. `GET /health*=always_off`.
+    if let Some(overrides) = var("OTEX_TRACES_SAMPLER_OVERRIDES") {
+        for (pattern, value) in parse_key_values(&overrides) {
+            let (sampler, argument) = match value.split_once(':') {
+                Some((sampler, argument)) => (sampler, Some(argument)),
+                None => (value.as_str(), None),
+            };
+            match parse_sampler(sampler, argument) {
+                Some(sampler) => config.span_samplers.push((pattern, sampler)),
+                None => log::warn!("ignoring unsupported sampler {:?} for span {:?}", value, pattern),
+            }
+        }
+    }
+
+    if let Some(filter) = var("OTEX_LOG") {
+        match filter.parse() {
+            Ok(filter) => config.log_filter = Some(filter),
+            Err(e) => log::warn!("ignoring OTEX_LOG: {}", e),
+        }
+    }
+
+    // `random`, `sequential` or `seeded:<seed>`.
+    if let Some(id_generator) = var("OTEX_ID_GENERATOR") {
+        match parse_id_generator(&id_generator) {
+            Some(id_generator) => config.id_generator = id_generator,
+            None => log::warn!("ignoring unsupported OTEX_ID_GENERATOR {:?}", id_generator),
+        }
+    }
+
+    if var("OTEL_SDK_DISABLED").is_some_and(|disabled| disabled.eq_ignore_ascii_case("true")) {
+        config.traces.enabled = false;
+        config.logs.enabled = false;
+        config.metrics.enabled = false;
+    }
+
+    builder
+}
+
+/// Applies the switches turning signals off, which [`crate::try_init`] honors
+/// even for a config not loaded with [`crate::Config::from_env`]. Turning a
+/// signal back on is left to `from_env`, so code can still disable it.
+pub(crate) fn apply_switches(config: &mut Config) {
+    switches_from(config, &|name| std::env::var(name).ok().map(|value| value.trim().to_string()));
+}
+
+fn switches_from(config: &mut Config, var: &dyn Fn(&str) -> Option<String>) {
+    // Legacy switch turning off OTLP export for every signal, superseded by
+    // `OTEX_{TRACES,LOGS,METRICS}_ENABLED`.
+    let otex_export = var("OTEX_EXPORT").is_some_and(|value| value.eq_ignore_ascii_case("false"));
+    let signals = [
+        ("TRACES", &mut config.traces),
+        ("LOGS", &mut config.logs),
+        ("METRICS", &mut config.metrics),
+    ];
+    for (signal, signal_config) in signals {
+        let disabled = var(&format!("OTEX_{}_ENABLED", signal))
+            .is_some_and(|value| value.eq_ignore_ascii_case("false"));
+        if disabled || (otex_export && signal_config.exporter == Exporter::Otlp) {
+            signal_config.enabled = false;
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(var: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<T> {
+    let value = var(name)?;
+    value
+        .parse()
+        .inspect_err(|_| log::warn!("ignoring invalid {} {:?}", name, value))
+        .ok()
+}
+
+fn millis(var: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<Duration> {
+    number(var, name).map(Duration::from_millis)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_exporter(value: &str) -> Option<Exporter> {
+    // Only the first exporter of a comma separated list is used.
+    match value.split(',').next()?.trim() {
+        "otlp" => Some(Exporter::Otlp),
+        "console" => Some(Exporter::Console),
+        #[cfg(feature = "stdout")]
+        "stdout" => Some(Exporter::Stdout),
+        #[cfg(feature = "file")]
+        "file" => Some(Exporter::File),
+        "none" => Some(Exporter::None),
+        _ => None,
+    }
+}
+
+fn parse_protocol(value: &str) -> Option<Protocol> {
+    match value {
+        "grpc" => Some(Protocol::Grpc),
+        "http/protobuf" => Some(Protocol::HttpProtobuf),
+        "http/json" => Some(Protocol::HttpJson),
+        _ => None,
+    }
+}
+
+fn parse_id_generator(value: &str) -> Option<IdGenerator> {
+    match value.trim().to_lowercase().as_str() {
+        "random" => Some(IdGenerator::Random),
+        "sequential" => Some(IdGenerator::Sequential),
+        other => other.strip_prefix("seeded:")?.parse().ok().map(IdGenerator::Seeded),
+    }
+}
+
+fn parse_sampler(value: &str, argument: Option<&str>) -> Option<Sampler> {
+    let ratio = || match argument.map(str::parse::<f64>) {
+        None => 1.0,
+        Some(Ok(ratio)) if (0.0..=1.0).contains(&ratio) => ratio,
+        Some(_) => {
+            log::warn!("ignoring invalid sampler argument {:?}", argument);
+            1.0
+        }
+    };
+
+    let sampler = match value {
+        "always_on" => Sampler::AlwaysOn,
+        "always_off" => Sampler::AlwaysOff,
+        "traceidratio" => Sampler::TraceIdRatio(ratio()),
+        "parentbased_always_on" => Sampler::ParentBased(Box::new(Sampler::AlwaysOn)),
+        "parentbased_always_off" => Sampler::ParentBased(Box::new(Sampler::AlwaysOff)),
+        "parentbased_traceidratio" => Sampler::ParentBased(Box::new(Sampler::TraceIdRatio(ratio()))),
+        _ => return None,
+    };
+    Some(sampler)
+}
+
+/// Parses `key1=value1,key2=value2`, percent-decoding keys and values.
+fn parse_key_values(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = percent_decode(key.trim())?;
+            let value = percent_decode(value.trim())?;
+            (!key.is_empty()).then_some((key, value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let high = (input.next()? as char).to_digit(16)?;
+            let low = (input.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use opentelemetry::{Key, Value};
+
+    use super::{load_from, switches_from};
+    use crate::config::{
+        BatchConfig, Config, Exporter, FileRotation, IdGenerator, Protocol, RetryQueue,
+        RotationInterval, Sampler,
+    };
+
+    fn config_from(vars: &[(&str, &str)]) -> Config {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        load_from(Config::builder(), |name| vars.get(name).cloned()).build()
+    }
+
+    #[test]
+    fn service_and_resource() {
+        let config = config_from(&[
+            ("OTEL_RESOURCE_ATTRIBUTES", "service.name=ignored,service.version=2.0,team=core%2Cinfra"),
+            ("OTEL_SERVICE_NAME", "billing"),
+        ]);
+
+        assert_eq!(config.service_name(), "billing");
+        assert_eq!(config.service_version(), Some("2.0"));
+        assert_eq!(config.resource_attributes.len(), 1);
+        assert_eq!(config.resource_attributes[0].key, Key::new("team"));
+        assert_eq!(config.resource_attributes[0].value, Value::from("core,infra"));
+    }
+
+    #[test]
+    fn exporters() {
+        let config = config_from(&[
+            ("OTEL_TRACES_EXPORTER", "none"),
+            ("OTEL_LOGS_EXPORTER", "zipkin"),
+            ("OTEL_METRICS_EXPORTER", "console"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("OTEL_EXPORTER_OTLP_METRICS_ENDPOINT", "http://metrics:4318/v1/metrics"),
+        ]);
+
+        assert_eq!(config.traces.exporter, Exporter::None);
+        assert_eq!(config.logs.exporter, Exporter::Otlp);
+        assert_eq!(config.metrics.exporter, Exporter::Console);
+        assert_eq!(config.endpoint.as_deref(), Some("http://collector:4318"));
+        assert_eq!(config.metrics.endpoint.as_deref(), Some("http://metrics:4318/v1/metrics"));
+
+        let config = config_from(&[("OTEX_EXPORT", "FALSE")]);
+        assert!(!config.metrics.enabled);
+
+        // Also applied by `try_init` to configs not loaded from the environment.
+        let mut config = Config::builder().with_traces_exporter(Exporter::Console).build();
+        switches_from(&mut config, &|name| (name == "OTEX_EXPORT").then(|| "false".to_string()));
+        assert!(config.traces.enabled);
+        assert!(!config.logs.enabled && !config.metrics.enabled);
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn file_exporter() {
+        let config = config_from(&[
+            ("OTEL_TRACES_EXPORTER", "file"),
+            ("OTEX_FILE_PATH", "/var/log/otex/telemetry.jsonl"),
+        ]);
+
+        assert_eq!(config.traces.exporter, Exporter::File);
+        assert_eq!(config.file_path, Some("/var/log/otex/telemetry.jsonl".into()));
+    }
+
+    #[test]
+    fn file_rotation() {
+        let config = config_from(&[
+            ("OTEX_FILE_MAX_BYTES", "1048576"),
+            ("OTEX_FILE_ROTATION", "Daily"),
+            ("OTEX_FILE_MAX_FILES", "7"),
+            ("OTEX_FILE_COMPRESSION", "zstd"),
+        ]);
+
+        let rotation = FileRotation::new()
+            .with_max_bytes(1024 * 1024)
+            .with_interval(RotationInterval::Daily)
+            .with_max_files(7);
+        assert_eq!(config.file_rotation, rotation);
+    }
+
+    #[test]
+    fn protocols() {
+        let config = config_from(&[
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "grpc"),
+            ("OTEL_EXPORTER_OTLP_LOGS_PROTOCOL", "http/json"),
+            ("OTEL_EXPORTER_OTLP_METRICS_PROTOCOL", "thrift"),
+        ]);
+
+        assert_eq!(config.traces.protocol, Protocol::Grpc);
+        assert_eq!(config.logs.protocol, Protocol::HttpJson);
+        assert_eq!(config.metrics.protocol, Protocol::default());
+    }
+
+    #[test]
+    fn otlp_requests() {
+        let config = config_from(&[
+            ("OTEL_EXPORTER_OTLP_HEADERS", "api-key=secret,authorization=Basic%20dXNlcg=="),
+            ("OTEL_EXPORTER_OTLP_COMPRESSION", "gzip"),
+            ("OTEL_EXPORTER_OTLP_TIMEOUT", "2500"),
+            ("OTEL_EXPORTER_OTLP_CERTIFICATE", "/etc/otel/ca.pem"),
+            ("OTEX_RETRY_QUEUE_DIR", "/var/spool/otex"),
+            ("OTEX_RETRY_QUEUE_MAX_BYTES", "1048576"),
+        ]);
+
+        assert_eq!(
+            config.otlp.headers,
+            [
+                ("api-key".to_string(), "secret".to_string()),
+                ("authorization".to_string(), "Basic dXNlcg==".to_string()),
+            ]
+        );
+        assert!(config.otlp.gzip);
+        assert_eq!(config.otlp.timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(config.otlp.ca_certificate, Some("/etc/otel/ca.pem".into()));
+        assert_eq!(config.otlp.client_certificate, None);
+        assert_eq!(
+            config.retry_queue,
+            Some(RetryQueue::new("/var/spool/otex").with_max_bytes(1024 * 1024))
+        );
+    }
+
+    #[test]
+    fn sampler() {
+        let config = config_from(&[
+            ("OTEL_TRACES_SAMPLER", "parentbased_traceidratio"),
+            ("OTEL_TRACES_SAMPLER_ARG", "0.25"),
+        ]);
+        assert_eq!(
+            config.sampler,
+            Sampler::ParentBased(Box::new(Sampler::TraceIdRatio(0.25)))
+        );
+
+        let config = config_from(&[("OTEL_TRACES_SAMPLER", "traceidratio"), ("OTEL_TRACES_SAMPLER_ARG", "2")]);
+        assert_eq!(config.sampler, Sampler::TraceIdRatio(1.0));
+
+        let config = config_from(&[("OTEL_TRACES_SAMPLER", "jaeger_remote")]);
+        assert_eq!(config.sampler, Sampler::default());
+
+        let config = config_from(&[(
+            "OTEX_TRACES_SAMPLER_OVERRIDES",
+            "GET /health*=always_off,checkout=traceidratio:0.5,login=unknown",
+        )]);
+        assert_eq!(
+            config.span_samplers,
+            [
+                ("GET /health*".to_string(), Sampler::AlwaysOff),
+                ("checkout".to_string(), Sampler::TraceIdRatio(0.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn id_generator() {
+        let config = config_from(&[("OTEX_ID_GENERATOR", "sequential")]);
+        assert_eq!(config.id_generator, IdGenerator::Sequential);
+
+        let config = config_from(&[("OTEX_ID_GENERATOR", "seeded:42")]);
+        assert_eq!(config.id_generator, IdGenerator::Seeded(42));
+
+        let config = config_from(&[("OTEX_ID_GENERATOR", "seeded:x")]);
+        assert_eq!(config.id_generator, IdGenerator::Random);
+    }
+
+    #[test]
+    fn log_filter() {
+        let config = config_from(&[("OTEX_LOG", "info,my_crate::db=debug")]);
+        assert_eq!(config.log_filter, Some("info,my_crate::db=debug".parse().unwrap()));
+
+        let config = config_from(&[("OTEX_LOG", "my_crate=loud")]);
+        assert_eq!(config.log_filter, None);
+    }
+
+    #[test]
+    fn batching() {
+        let config = config_from(&[
+            ("OTEL_BSP_SCHEDULE_DELAY", "500"),
+            ("OTEL_BSP_MAX_QUEUE_SIZE", "4096"),
+            ("OTEL_BLRP_MAX_EXPORT_BATCH_SIZE", "128"),
+            ("OTEL_BLRP_MAX_QUEUE_SIZE", "-1"),
+            ("OTEL_METRIC_EXPORT_INTERVAL", "15000"),
+        ]);
+
+        assert_eq!(
+            config.traces.batch,
+            BatchConfig::new()
+                .with_scheduled_delay(Duration::from_millis(500))
+                .with_max_queue_size(4096)
+        );
+        assert_eq!(config.logs.batch, BatchConfig::new().with_max_export_batch_size(128));
+        assert_eq!(config.metrics.export_interval, Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn signals_enabled() {
+        let config = config_from(&[
+            ("OTEX_TRACES_ENABLED", "false"),
+            ("OTEX_LOGS_ENABLED", "TRUE"),
+            ("OTEX_METRICS_ENABLED", "nope"),
+        ]);
+
+        assert!(!config.traces.enabled);
+        assert!(config.logs.enabled && config.metrics.enabled);
+
+        // Only turning a signal off applies without `from_env`.
+        let vars = |name: &str| match name {
+            "OTEX_LOGS_ENABLED" => Some("false".to_string()),
+            "OTEX_METRICS_ENABLED" => Some("true".to_string()),
+            _ => None,
+        };
+        let mut config = Config::builder().with_metrics(false).build();
+        switches_from(&mut config, &vars);
+        assert!(config.traces.enabled);
+        assert!(!config.logs.enabled && !config.metrics.enabled);
+    }
+
+    #[test]
+    fn sdk_disabled() {
+        let config = config_from(&[("OTEL_SDK_DISABLED", "true")]);
+
+        assert!(!config.traces.enabled && !config.logs.enabled && !config.metrics.enabled);
+    }
+}
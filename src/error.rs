@@ -0,0 +1,70 @@
+use std::fmt;
+
+use opentelemetry_sdk::error::OTelSdkError;
+
+/// Errors returned by [`crate::try_init`] and [`crate::OtexGuard::shutdown`].
+#[derive(Debug)]
+pub enum OtexError {
+    /// otex has already been initialized in this process.
+    AlreadyInitialized,
+    /// An exporter could not be built.
+    ExporterBuild {
+        signal: &'static str,
+        source: opentelemetry_otlp::ExporterBuildError,
+    },
+    /// The [`crate::Config`] is not usable.
+    InvalidConfig(String),
+    /// A file could not be opened, such as the file exporter's output.
+    File {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    /// The log filter file could not be watched for changes.
+    Watch(std::io::Error),
+    /// One or more signals failed to shut down.
+    Shutdown(Vec<(&'static str, OTelSdkError)>),
+    /// One or more signals failed to flush.
+    Flush(Vec<(&'static str, OTelSdkError)>),
+}
+
+impl fmt::Display for OtexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtexError::AlreadyInitialized => write!(f, "otex is already initialized"),
+            OtexError::ExporterBuild { signal, source } => {
+                write!(f, "failed to build {} exporter: {}", signal, source)
+            }
+            OtexError::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            OtexError::File { path, source } => {
+                write!(f, "failed to open {}: {}", path.display(), source)
+            }
+            OtexError::Watch(source) => write!(f, "failed to watch the log filter: {}", source),
+            OtexError::Shutdown(failed) => write_failed(f, "failed to shut down", failed),
+            OtexError::Flush(failed) => write_failed(f, "failed to flush", failed),
+        }
+    }
+}
+
+fn write_failed(
+    f: &mut fmt::Formatter<'_>,
+    message: &str,
+    failed: &[(&'static str, OTelSdkError)],
+) -> fmt::Result {
+    write!(f, "{}", message)?;
+    for (i, (signal, error)) in failed.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        write!(f, "{}{}: {}", separator, signal, error)?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for OtexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OtexError::ExporterBuild { source, .. } => Some(source),
+            OtexError::File { source, .. } => Some(source),
+            OtexError::Watch(source) => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! JSON Lines file exporters.
+//!
+//! Each export batch becomes one line holding the OTLP-JSON encoding of an
+//! `ExportTraceServiceRequest`, `ExportLogsServiceRequest` or
+//! `ExportMetricsServiceRequest`, the same payload the OTLP/HTTP JSON exporter
+//! sends. Lines can be told apart by their `resourceSpans`, `resourceLogs` or
+//! `resourceMetrics` key and replayed to a collector as is.
+//!
+//! All signals configured with the same path share one [`FileSink`], which
+//! rotates the file according to [`FileRotation`].
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+use lazy_static::lazy_static;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::logs::LogBatch;
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::trace::SpanData;
+
+use crate::config::{Config, FileRotation, RotationInterval};
+use crate::error::OtexError;
+
+lazy_static! {
+    static ref SINKS: Mutex<HashMap<PathBuf, Weak<Mutex<Writer>>>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the sink for the configured path, opening it unless another signal
+/// already did.
+pub(crate) fn sink(config: &Config) -> Result<FileSink, OtexError> {
+    let path = config
+        .file_path
+        .clone()
+        .ok_or_else(|| OtexError::InvalidConfig("file exporter requires a file path".to_string()))?;
+    if cfg!(not(feature = "gzip")) && config.file_rotation.gzip {
+        return Err(OtexError::InvalidConfig(
+            "gzip of rotated files requires the `gzip` feature".to_string(),
+        ));
+    }
+
+    let mut sinks = SINKS.lock().unwrap();
+    if let Some(writer) = sinks.get(&path).and_then(Weak::upgrade) {
+        return Ok(FileSink { writer });
+    }
+
+    let writer = Writer::open(path.clone(), config.file_rotation.clone())
+        .map_err(|source| OtexError::File { path: path.clone(), source })?;
+    let writer = Arc::new(Mutex::new(writer));
+    sinks.retain(|_, writer| writer.strong_count() > 0);
+    sinks.insert(path, Arc::downgrade(&writer));
+    Ok(FileSink { writer })
+}
+
+/// An append-only JSON Lines file shared by the file exporters.
+#[derive(Debug, Clone)]
+pub(crate) struct FileSink {
+    writer: Arc<Mutex<Writer>>,
+}
+
+impl FileSink {
+    /// Appends `value` as a single line.
+    fn write(&self, value: &impl serde::Serialize) -> OTelSdkResult {
+        let mut line = serde_json::to_vec(value)
+            .map_err(|e| OTelSdkError::InternalFailure(format!("failed to encode batch: {}", e)))?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().unwrap();
+        writer.write(&line).map_err(|e| writer.failure(e))
+    }
+
+    fn flush(&self) -> OTelSdkResult {
+        let mut writer = self.writer.lock().unwrap();
+        writer.file.flush().map_err(|e| writer.failure(e))
+    }
+}
+
+#[derive(Debug)]
+struct Writer {
+    path: PathBuf,
+    rotation: FileRotation,
+    file: File,
+    size: u64,
+    /// The rotation period the active file was last written in.
+    period: Option<u64>,
+}
+
+impl Writer {
+    fn open(path: PathBuf, rotation: FileRotation) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // A file left over from an earlier period is rotated on the first write.
+        let period = metadata.modified().ok().and_then(|modified| period(&rotation, modified));
+        Ok(Self {
+            path,
+            rotation,
+            file,
+            size: metadata.len(),
+            period,
+        })
+    }
+
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let period = period(&self.rotation, SystemTime::now());
+        let too_large = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max_bytes| self.size > 0 && self.size + line.len() as u64 > max_bytes);
+        if too_large || (self.size > 0 && period != self.period) {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        self.period = period;
+        Ok(())
+    }
+
+    /// Shifts the rotated files up by one, dropping those past the retention
+    /// count, and moves the active file to `<path>.1`.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        let mut count = 0;
+        while self.rotated(count + 1).is_some() {
+            count += 1;
+        }
+        let keep = self.rotation.max_files.unwrap_or(usize::MAX);
+        for index in (1..=count).rev() {
+            let Some((from, gzip)) = self.rotated(index) else { continue };
+            if index >= keep {
+                std::fs::remove_file(from)?;
+            } else {
+                std::fs::rename(from, self.rotated_path(index + 1, gzip))?;
+            }
+        }
+
+        if keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let rotated = self.rotated_path(1, false);
+            std::fs::rename(&self.path, &rotated)?;
+            if self.rotation.gzip {
+                compress(&rotated, &self.rotated_path(1, true))?;
+                std::fs::remove_file(&rotated)?;
+            }
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Returns the rotated file with the given index and whether it is gzipped.
+    fn rotated(&self, index: usize) -> Option<(PathBuf, bool)> {
+        [false, true]
+            .into_iter()
+            .map(|gzip| (self.rotated_path(index, gzip), gzip))
+            .find(|(path, _)| path.exists())
+    }
+
+    fn rotated_path(&self, index: usize, gzip: bool) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        if gzip {
+            path.push(".gz");
+        }
+        path.into()
+    }
+
+    fn failure(&self, error: std::io::Error) -> OTelSdkError {
+        OTelSdkError::InternalFailure(format!("failed to write {}: {}", self.path.display(), error))
+    }
+}
+
+/// The number of whole rotation intervals since the epoch, if rotating by time.
+fn period(rotation: &FileRotation, time: SystemTime) -> Option<u64> {
+    let interval = match rotation.interval? {
+        RotationInterval::Hourly => 60 * 60,
+        RotationInterval::Daily => 24 * 60 * 60,
+    };
+    let elapsed = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    Some(elapsed.as_secs() / interval)
+}
+
+#[cfg(feature = "gzip")]
+fn compress(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut source = File::open(from)?;
+    let mut encoder = flate2::write::GzEncoder::new(File::create(to)?, flate2::Compression::default());
+    std::io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+/// Rejected when the sink is opened, but writers can be built without it.
+#[cfg(not(feature = "gzip"))]
+fn compress(_from: &Path, _to: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "gzip feature disabled"))
+}
+
+#[derive(Debug)]
+pub(crate) struct FileSpanExporter {
+    sink: FileSink,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl FileSpanExporter {
+    pub(crate) fn new(sink: FileSink) -> Self {
+        Self {
+            sink,
+            resource: ResourceAttributesWithSchema::default(),
+        }
+    }
+}
+
+impl opentelemetry_sdk::trace::SpanExporter for FileSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        use opentelemetry_proto::transform::trace::tonic::group_spans_by_resource_and_scope;
+
+        self.sink.write(&ExportTraceServiceRequest {
+            resource_spans: group_spans_by_resource_and_scope(batch, &self.resource),
+        })
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.sink.flush()
+    }
+
+    fn shutdown_with_timeout(&mut self, _timeout: Duration) -> OTelSdkResult {
+        self.sink.flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FileLogExporter {
+    sink: FileSink,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl FileLogExporter {
+    pub(crate) fn new(sink: FileSink) -> Self {
+        Self {
+            sink,
+            resource: ResourceAttributesWithSchema::default(),
+        }
+    }
+}
+
+impl opentelemetry_sdk::logs::LogExporter for FileLogExporter {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        use opentelemetry_proto::transform::logs::tonic::group_logs_by_resource_and_scope;
+
+        self.sink.write(&ExportLogsServiceRequest {
+            resource_logs: group_logs_by_resource_and_scope(batch, &self.resource),
+        })
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        self.sink.flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FileMetricExporter {
+    sink: FileSink,
+}
+
+impl FileMetricExporter {
+    pub(crate) fn new(sink: FileSink) -> Self {
+        Self { sink }
+    }
+}
+
+impl opentelemetry_sdk::metrics::exporter::PushMetricExporter for FileMetricExporter {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        self.sink.write(&ExportMetricsServiceRequest::from(metrics))
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.sink.flush()
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        self.sink.flush()
+    }
+
+    fn temporality(&self) -> Temporality {
+        Temporality::Cumulative
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{Span, Tracer, TracerProvider};
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    use super::{FileSpanExporter, Writer, period, sink};
+    use crate::config::{Config, FileRotation, RotationInterval};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("otex-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn spans_as_json_lines() {
+        let dir = temp_dir("file");
+        let path = dir.join("telemetry.jsonl");
+        let config = Config::builder().with_file_path(&path).build();
+
+        let sink = sink(&config).unwrap();
+        let provider = SdkTracerProvider::builder()
+            .with_resource(
+                opentelemetry_sdk::Resource::builder_empty()
+                    .with_attribute(KeyValue::new("service.name", "billing"))
+                    .build(),
+            )
+            .with_simple_exporter(FileSpanExporter::new(sink.clone()))
+            .build();
+        let tracer = provider.tracer("test");
+        tracer.start("first").end();
+        tracer.start("second").end();
+        provider.shutdown().unwrap();
+
+        // A second signal on the same path shares the open file.
+        assert!(std::sync::Arc::ptr_eq(&super::sink(&config).unwrap().writer, &sink.writer));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        let resource_spans = &lines[1]["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0]["value"]["stringValue"],
+            "billing"
+        );
+        assert_eq!(resource_spans["scopeSpans"][0]["spans"][0]["name"], "second");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_max_files() {
+        let dir = temp_dir("rotation");
+        let path = dir.join("telemetry.jsonl");
+        let rotation = FileRotation::new().with_max_bytes(10).with_max_files(2);
+        let mut writer = Writer::open(path.clone(), rotation).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            writer.write(line.as_bytes()).unwrap();
+        }
+
+        let read = |suffix: &str| std::fs::read_to_string(dir.join(format!("telemetry.jsonl{}", suffix)));
+        assert_eq!(read("").unwrap(), "fourth\n");
+        assert_eq!(read(".1").unwrap(), "third\n");
+        assert_eq!(read(".2").unwrap(), "second\n");
+        assert!(read(".3").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_periods() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(2 * 86_400 + 3 * 3_600 + 5);
+        let hourly = FileRotation::new().with_interval(RotationInterval::Hourly);
+        let daily = FileRotation::new().with_interval(RotationInterval::Daily);
+
+        assert_eq!(period(&FileRotation::new(), time), None);
+        assert_eq!(period(&hourly, time), Some(51));
+        assert_eq!(period(&daily, time), Some(2));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzips_rotated_files() {
+        use std::io::Read;
+
+        let dir = temp_dir("gzip");
+        let path = dir.join("telemetry.jsonl");
+        let rotation = FileRotation::new().with_max_bytes(1).with_gzip(true);
+        let mut writer = Writer::open(path.clone(), rotation).unwrap();
+        writer.write(b"first\n").unwrap();
+        writer.write(b"second\n").unwrap();
+
+        let rotated = std::fs::File::open(dir.join("telemetry.jsonl.1.gz")).unwrap();
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(rotated).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "first\n");
+        assert!(!dir.join("telemetry.jsonl.1").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
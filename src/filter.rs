@@ -0,0 +1,392 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, mpsc};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use log::LevelFilter;
+use opentelemetry::logs::Severity;
+
+use crate::error::OtexError;
+
+/// Per-module severity filter for the otex log macros, written like
+/// `RUST_LOG`: `info,my_crate::db=debug,hyper=off`.
+///
+/// A bare level applies to every module, `module=level` to a module and its
+/// submodules, and a bare module enables everything in it. The most specific
+/// module wins; modules matching no directive are disabled unless a bare
+/// level is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    /// Sorted by decreasing module length, so the first match is the most specific.
+    directives: Vec<(String, LevelFilter)>,
+    default: LevelFilter,
+}
+
+impl LogFilter {
+    /// Whether a record of `severity` logged from `module_path` passes.
+    pub fn enabled(&self, module_path: &str, severity: Severity) -> bool {
+        let level = self
+            .directives
+            .iter()
+            .find(|(module, _)| matches_module(module_path, module))
+            .map_or(self.default, |(_, level)| *level);
+        level.to_level().is_some_and(|level| severity >= min_severity(level))
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = OtexError;
+
+    fn from_str(filter: &str) -> Result<Self, OtexError> {
+        let invalid = |directive: &str| {
+            OtexError::InvalidConfig(format!("invalid log filter directive {:?}", directive))
+        };
+
+        let mut directives = Vec::new();
+        let mut default = LevelFilter::Off;
+        for directive in filter.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = level.trim().parse().map_err(|_| invalid(directive))?;
+                    let module = module.trim();
+                    if module.is_empty() {
+                        return Err(invalid(directive));
+                    }
+                    // A later directive for the same module replaces the earlier one.
+                    directives.retain(|(other, _)| other != module);
+                    directives.push((module.to_string(), level));
+                }
+                None => match directive.parse() {
+                    Ok(level) => default = level,
+                    Err(_) => {
+                        directives.retain(|(other, _)| other != directive);
+                        directives.push((directive.to_string(), LevelFilter::Trace));
+                    }
+                },
+            }
+        }
+        directives.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+
+        Ok(Self { directives, default })
+    }
+}
+
+/// Formats the filter in the syntax it is parsed from.
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut directives = Vec::new();
+        if self.default != LevelFilter::Off || self.directives.is_empty() {
+            directives.push(self.default.as_str().to_lowercase());
+        }
+        for (module, level) in &self.directives {
+            directives.push(format!("{}={}", module, level.as_str().to_lowercase()));
+        }
+        f.write_str(&directives.join(","))
+    }
+}
+
+/// Changes the log filter at runtime, see [`crate::OtexGuard::log_filter`].
+///
+/// Clones share the same filter.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilterHandle {
+    filter: Arc<RwLock<Option<LogFilter>>>,
+}
+
+impl LogFilterHandle {
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn new(filter: Option<LogFilter>) -> Self {
+        Self {
+            filter: Arc::new(RwLock::new(filter)),
+        }
+    }
+
+    /// Replaces the filter.
+    pub fn set(&self, filter: LogFilter) {
+        *self.filter.write().unwrap() = Some(filter);
+    }
+
+    /// Removes the filter, letting every record through.
+    pub fn clear(&self) {
+        *self.filter.write().unwrap() = None;
+    }
+
+    /// Returns the filter in effect, if any.
+    pub fn current(&self) -> Option<LogFilter> {
+        self.filter.read().unwrap().clone()
+    }
+
+    pub(crate) fn enabled(&self, module_path: &str, severity: Severity) -> bool {
+        let filter = self.filter.read().unwrap();
+        filter.as_ref().is_none_or(|filter| filter.enabled(module_path, severity))
+    }
+
+    /// Sets the filter from a file holding directives separated by commas or
+    /// newlines. Lines starting with `#` are comments.
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<(), OtexError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| OtexError::File {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let directives: Vec<&str> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        self.set(directives.join(",").parse()?);
+        Ok(())
+    }
+
+    /// Loads the filter from `path`, then reloads it whenever the file
+    /// changes, checking every `interval`. With the `sighup` feature, a SIGHUP
+    /// also reloads it. Reload errors are logged and keep the previous filter.
+    ///
+    /// Watching stops when the returned [`FilterWatcher`] is dropped. Once the
+    /// last watcher is gone, SIGHUP ends the process again, as by default.
+    pub fn watch_file(&self, path: impl Into<PathBuf>, interval: Duration) -> Result<FilterWatcher, OtexError> {
+        let path = path.into();
+        // Taken before loading, so changes made from now on are not missed.
+        let mut modified = modified_time(&path);
+        self.load_file(&path)?;
+
+        let hangup = Arc::new(AtomicBool::new(false));
+        #[cfg(all(unix, feature = "sighup"))]
+        let signal = sighup::register(hangup.clone()).map_err(OtexError::Watch)?;
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = self.clone();
+        let thread = std::thread::Builder::new()
+            .name("otex-log-filter".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let now_modified = modified_time(&path);
+                    let changed = now_modified != modified;
+                    if changed || hangup.swap(false, Ordering::Relaxed) {
+                        modified = now_modified;
+                        match handle.load_file(&path) {
+                            Ok(()) => log::info!("reloaded log filter from {}", path.display()),
+                            Err(e) => log::warn!("keeping the log filter: {}", e),
+                        }
+                    }
+                }
+            })
+            .map_err(OtexError::Watch)?;
+
+        Ok(FilterWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+            #[cfg(all(unix, feature = "sighup"))]
+            signal,
+        })
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the log filter from a file, see [`LogFilterHandle::watch_file`].
+///
+/// Dropping the last watcher restores the default SIGHUP action.
+#[must_use = "dropping the watcher stops reloading the filter"]
+#[derive(Debug)]
+pub struct FilterWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    #[cfg(all(unix, feature = "sighup"))]
+    signal: signal_hook::SigId,
+}
+
+impl Drop for FilterWatcher {
+    fn drop(&mut self) {
+        #[cfg(all(unix, feature = "sighup"))]
+        sighup::unregister(self.signal);
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Tracks the SIGHUP registrations of the watchers. signal-hook keeps its
+/// handler installed after unregistering, so the default action, ending the
+/// process, is emulated while no watcher is registered.
+#[cfg(all(unix, feature = "sighup"))]
+mod sighup {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use signal_hook::SigId;
+    use signal_hook::consts::SIGHUP;
+
+    /// The number of watchers, and the flag running the default action.
+    static STATE: Mutex<(usize, Option<Arc<AtomicBool>>)> = Mutex::new((0, None));
+
+    pub(super) fn register(hangup: Arc<AtomicBool>) -> std::io::Result<SigId> {
+        let mut state = STATE.lock().unwrap();
+        let default = match &state.1 {
+            Some(default) => default.clone(),
+            None => {
+                let default = Arc::new(AtomicBool::new(false));
+                signal_hook::flag::register_conditional_default(SIGHUP, default.clone())?;
+                state.1 = Some(default.clone());
+                default
+            }
+        };
+        let id = signal_hook::flag::register(SIGHUP, hangup)?;
+        state.0 += 1;
+        default.store(false, Ordering::SeqCst);
+        Ok(id)
+    }
+
+    pub(super) fn unregister(id: SigId) {
+        let mut state = STATE.lock().unwrap();
+        signal_hook::low_level::unregister(id);
+        state.0 -= 1;
+        if let (0, Some(default)) = &*state {
+            default.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn default_restored() -> bool {
+        let state = STATE.lock().unwrap();
+        state.0 == 0 && state.1.as_ref().is_some_and(|default| default.load(Ordering::SeqCst))
+    }
+}
+
+/// Whether `module_path` is `module` or one of its submodules.
+fn matches_module(module_path: &str, module: &str) -> bool {
+    module_path
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+fn min_severity(level: log::Level) -> Severity {
+    match level {
+        log::Level::Error => Severity::Error,
+        log::Level::Warn => Severity::Warn,
+        log::Level::Info => Severity::Info,
+        log::Level::Debug => Severity::Debug,
+        log::Level::Trace => Severity::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use opentelemetry::logs::Severity;
+
+    use super::{LogFilter, LogFilterHandle};
+
+    #[test]
+    fn module_directives() {
+        let filter: LogFilter = "info, my_crate::db=debug,hyper=off,my_crate::db=trace".parse().unwrap();
+
+        assert!(filter.enabled("my_crate", Severity::Info));
+        assert!(!filter.enabled("my_crate", Severity::Debug));
+        assert!(filter.enabled("my_crate::db::pool", Severity::Trace));
+        assert!(!filter.enabled("my_crate::dbx", Severity::Debug));
+        assert!(!filter.enabled("hyper::client", Severity::Fatal));
+        assert!(filter.enabled("other", Severity::Warn2));
+    }
+
+    #[test]
+    fn without_default_level() {
+        let filter: LogFilter = "my_crate=warn,tokio".parse().unwrap();
+
+        assert!(filter.enabled("my_crate", Severity::Error));
+        assert!(!filter.enabled("my_crate", Severity::Info));
+        assert!(filter.enabled("tokio::runtime", Severity::Trace));
+        assert!(!filter.enabled("other", Severity::Fatal));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for filter in ["info,my_crate::db=debug,hyper=off", "my_crate=warn", "off"] {
+            let parsed: LogFilter = filter.parse().unwrap();
+            assert_eq!(parsed.to_string(), filter);
+        }
+        let parsed: LogFilter = "tokio, INFO".parse().unwrap();
+        assert_eq!(parsed.to_string(), "info,tokio=trace");
+    }
+
+    #[test]
+    fn handle() {
+        let handle = LogFilterHandle::default();
+        assert!(handle.enabled("my_crate", Severity::Trace));
+
+        handle.set("warn".parse().unwrap());
+        assert!(!handle.clone().enabled("my_crate", Severity::Info));
+        assert_eq!(handle.current().unwrap().to_string(), "warn");
+
+        handle.clear();
+        assert_eq!(handle.current(), None);
+    }
+
+    #[test]
+    fn watches_file() {
+        let path = std::env::temp_dir().join(format!("otex-log-filter-{}", std::process::id()));
+        let modified = SystemTime::now();
+        write(&path, "# levels\ninfo\nmy_crate::db=debug\n", modified);
+
+        let handle = LogFilterHandle::default();
+        let watcher = handle.watch_file(&path, Duration::from_millis(10)).unwrap();
+        assert_eq!(handle.current().unwrap().to_string(), "info,my_crate::db=debug");
+
+        // An explicit, later modification time does not depend on the
+        // filesystem's timestamp resolution.
+        write(&path, "error", modified + Duration::from_secs(1));
+        wait_for(|| handle.current().unwrap().to_string() == "error");
+
+        #[cfg(all(unix, feature = "sighup"))]
+        {
+            // Keep the modification time, so only the signal triggers the reload.
+            write(&path, "trace", modified + Duration::from_secs(1));
+            signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+            wait_for(|| handle.current().unwrap().to_string() == "trace");
+        }
+
+        // Dropping the watcher joins its thread, so nothing reloads afterwards.
+        let current = handle.current();
+        drop(watcher);
+        #[cfg(all(unix, feature = "sighup"))]
+        assert!(super::sighup::default_restored());
+        write(&path, "debug", modified + Duration::from_secs(2));
+        assert_eq!(handle.current(), current);
+
+        // Invalid files keep the previous filter.
+        write(&path, "my_crate=loud", modified + Duration::from_secs(3));
+        assert!(handle.load_file(&path).is_err());
+        assert_eq!(handle.current(), current);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(handle.watch_file(&path, Duration::from_millis(10)).is_err());
+    }
+
+    fn write(path: &Path, contents: &str, modified: SystemTime) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn invalid_directives() {
+        assert!("my_crate=loud".parse::<LogFilter>().is_err());
+        assert!("=debug".parse::<LogFilter>().is_err());
+        assert_eq!("".parse::<LogFilter>().unwrap(), "off".parse().unwrap());
+    }
+}
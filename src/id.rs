@@ -0,0 +1,112 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry_sdk::trace::RandomIdGenerator;
+
+use crate::config::IdGenerator;
+
+/// The [`opentelemetry_sdk::trace::IdGenerator`] selected by [`IdGenerator`].
+#[derive(Debug)]
+pub(crate) enum Ids {
+    Random(RandomIdGenerator),
+    Sequential { traces: AtomicU64, spans: AtomicU64 },
+    Seeded(Mutex<SplitMix64>),
+}
+
+impl Ids {
+    pub(crate) fn new(id_generator: IdGenerator) -> Self {
+        match id_generator {
+            IdGenerator::Random => Ids::Random(RandomIdGenerator::default()),
+            IdGenerator::Sequential => Ids::Sequential {
+                traces: AtomicU64::new(1),
+                spans: AtomicU64::new(1),
+            },
+            IdGenerator::Seeded(seed) => Ids::Seeded(Mutex::new(SplitMix64(seed))),
+        }
+    }
+}
+
+impl opentelemetry_sdk::trace::IdGenerator for Ids {
+    fn new_trace_id(&self) -> TraceId {
+        match self {
+            Ids::Random(random) => random.new_trace_id(),
+            Ids::Sequential { traces, .. } => {
+                let id = traces.fetch_add(1, Ordering::Relaxed);
+                TraceId::from_bytes(u128::from(id).to_be_bytes())
+            }
+            Ids::Seeded(rng) => {
+                let mut rng = rng.lock().unwrap();
+                // All-zero IDs are invalid.
+                loop {
+                    let id = (u128::from(rng.next()) << 64) | u128::from(rng.next());
+                    if id != 0 {
+                        return TraceId::from_bytes(id.to_be_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        match self {
+            Ids::Random(random) => random.new_span_id(),
+            Ids::Sequential { spans, .. } => {
+                SpanId::from_bytes(spans.fetch_add(1, Ordering::Relaxed).to_be_bytes())
+            }
+            Ids::Seeded(rng) => {
+                let mut rng = rng.lock().unwrap();
+                loop {
+                    let id = rng.next();
+                    if id != 0 {
+                        return SpanId::from_bytes(id.to_be_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The SplitMix64 generator: small, fast and good enough for IDs.
+#[derive(Debug)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry_sdk::trace::IdGenerator as _;
+
+    use super::Ids;
+    use crate::config::IdGenerator;
+
+    #[test]
+    fn sequential_ids() {
+        let ids = Ids::new(IdGenerator::Sequential);
+        assert_eq!(ids.new_trace_id().to_string(), "00000000000000000000000000000001");
+        assert_eq!(ids.new_trace_id().to_string(), "00000000000000000000000000000002");
+        assert_eq!(ids.new_span_id().to_string(), "0000000000000001");
+        assert_eq!(ids.new_span_id().to_string(), "0000000000000002");
+    }
+
+    #[test]
+    fn seeded_ids_repeat() {
+        let first = Ids::new(IdGenerator::Seeded(42));
+        let second = Ids::new(IdGenerator::Seeded(42));
+        for _ in 0..3 {
+            assert_eq!(first.new_trace_id(), second.new_trace_id());
+            assert_eq!(first.new_span_id(), second.new_span_id());
+        }
+
+        let other = Ids::new(IdGenerator::Seeded(7));
+        assert_ne!(Ids::new(IdGenerator::Seeded(42)).new_trace_id(), other.new_trace_id());
+    }
+}
@@ -1,16 +1,47 @@
+pub mod config;
+mod clock;
+mod console;
+mod env;
+mod error;
+#[cfg(feature = "file")]
+mod file;
+mod filter;
+mod id;
+mod log_bridge;
 mod logger;
 mod macros;
 mod metric;
+mod otlp;
 pub mod propagation;
+pub mod resource;
+#[cfg(feature = "retry-queue")]
+mod retry;
+mod sampling;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod tracer;
-mod keyvalue;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
 
 pub use opentelemetry::trace::FutureExt;
 
-pub use init::{init, shutdown, meter, tracer, logger};
+pub use clock::{Clock, ManualClock};
+pub use config::{
+    BatchConfig, Config, ConfigBuilder, Exporter, FileRotation, IdGenerator, Protocol, RetryQueue,
+    RotationInterval, Sampler, TailSampling,
+};
+pub use error::OtexError;
+pub use filter::{FilterWatcher, LogFilter, LogFilterHandle};
 
+pub use init::{init, try_init, shutdown, shutdown_with_timeout, flush, meter, tracer, logger, try_meter, try_tracer, try_logger, log_enabled, OtexGuard};
+
+pub use log_bridge::LogBridge;
 pub use logger::create_log_record;
-pub use tracer::{new_span, new_span_with_parent, new_event};
+#[doc(hidden)]
+pub use logger::create_log_record_unfiltered;
+pub use tracer::{new_span, new_span_with_parent, new_event, new_error_event};
+#[cfg(feature = "tracing")]
+pub use tracing_layer::OtexLayer;
 
 pub use opentelemetry::{*};
 
@@ -20,96 +51,492 @@ pub(crate) mod init {
     use opentelemetry::metrics::{MeterProvider, Meter};
     use opentelemetry::logs::LoggerProvider;
     use opentelemetry_sdk::{self as sdk};
-    use std::sync::OnceLock;
+    use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+    use std::sync::{Arc, OnceLock, mpsc};
+    use std::time::{Duration, Instant, SystemTime};
 
     use lazy_static::lazy_static;
 
-    const APPLICATION_NAME: &str = "otex";
+    use crate::clock::Clock;
+    use crate::config::Config;
+    use crate::error::OtexError;
+    use crate::filter::LogFilterHandle;
+
     lazy_static! {
+        pub static ref APPLICATION_NAME: OnceLock<String> = OnceLock::new();
         pub static ref TRACER_PROVIDER: OnceLock<sdk::trace::SdkTracerProvider> = OnceLock::new();
         pub static ref LOGGER_PROVIDER: OnceLock<sdk::logs::SdkLoggerProvider> = OnceLock::new();
         pub static ref METER_PROVIDER: OnceLock<sdk::metrics::SdkMeterProvider> = OnceLock::new();
+        pub static ref CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+        pub static ref LOG_FILTER: LogFilterHandle = LogFilterHandle::default();
+        /// Back [`tracer`] and [`logger`] when their signal is disabled.
+        static ref NOOP_TRACER_PROVIDER: sdk::trace::SdkTracerProvider = sdk::trace::SdkTracerProvider::builder()
+            .with_sampler(sdk::trace::Sampler::AlwaysOff)
+            .build();
+        static ref NOOP_LOGGER_PROVIDER: sdk::logs::SdkLoggerProvider = sdk::logs::SdkLoggerProvider::builder().build();
+    }
+
+    /// Flushes and shuts down the providers when dropped.
+    ///
+    /// Keep the guard alive for as long as telemetry should be recorded,
+    /// typically by binding it in `main`. Use [`OtexGuard::shutdown`] to
+    /// observe shutdown errors; on drop they are only logged.
+    #[must_use = "dropping the guard immediately shuts otex down"]
+    pub struct OtexGuard {
+        shut_down: bool,
+    }
+
+    impl OtexGuard {
+        /// Flushes and shuts down all providers, reporting the signals that
+        /// failed to shut down.
+        pub fn shutdown(mut self) -> Result<(), OtexError> {
+            self.shut_down = true;
+            shutdown_providers()
+        }
+
+        /// Like [`OtexGuard::shutdown`], but gives up on signals that have not
+        /// finished draining within `timeout`.
+        pub fn shutdown_with_timeout(mut self, timeout: Duration) -> Result<(), OtexError> {
+            self.shut_down = true;
+            shutdown_with_timeout(timeout)
+        }
+
+        /// Returns a handle changing the log filter at runtime, e.g. to enable
+        /// debug records of a module without restarting.
+        pub fn log_filter(&self) -> LogFilterHandle {
+            LOG_FILTER.clone()
+        }
+
+        /// Exports buffered telemetry without shutting down. See [`flush`].
+        pub fn flush(&self, timeout: Duration) -> Result<(), OtexError> {
+            flush(timeout)
+        }
     }
 
-    pub fn init() {
-        let trace_provider = crate::tracer::init_tracing();
-        crate::init::TRACER_PROVIDER
-            .set(trace_provider)
-            .expect("failed to set tracer provider");
+    impl Drop for OtexGuard {
+        fn drop(&mut self) {
+            if self.shut_down {
+                return;
+            }
+            if let Err(e) = shutdown_providers() {
+                log::error!("{}", e);
+            }
+        }
+    }
+
+    /// Initializes the tracer, logger and meter providers.
+    ///
+    /// # Panics
+    /// Panics if [`try_init`] fails.
+    pub fn init(config: Config) -> OtexGuard {
+        try_init(config).expect("failed to initialize otex")
+    }
 
-        let log_provider = crate::logger::init_logging();
-        crate::init::LOGGER_PROVIDER
-            .set(log_provider)
-            .expect("failed to set logger provider");
+    /// Initializes the tracer, logger and meter providers, returning an error
+    /// instead of panicking if otex is already initialized or an exporter
+    /// cannot be built.
+    ///
+    /// `OTEX_EXPORT=false` and `OTEX_{TRACES,LOGS,METRICS}_ENABLED=false` turn
+    /// signals off whether or not `config` was loaded with [`Config::from_env`].
+    pub fn try_init(mut config: Config) -> Result<OtexGuard, OtexError> {
+        crate::env::apply_switches(&mut config);
+        config.validate()?;
+        if APPLICATION_NAME.get().is_some() {
+            return Err(OtexError::AlreadyInitialized);
+        }
 
-        let meter_provider = crate::metric::init_metrics();
-        crate::init::METER_PROVIDER
-            .set(meter_provider)
-            .expect("failed to set logger provider");
+        let resource = crate::resource::build_resource(&config);
+        let trace_provider = crate::tracer::init_tracing(&config, &resource)?;
+        let log_provider = crate::logger::init_logging(&config, &resource)?;
+        let meter_provider = crate::metric::init_metrics(&config, &resource)?;
+        install(config, trace_provider, log_provider, meter_provider)?;
+
+        Ok(OtexGuard { shut_down: false })
     }
 
+    /// Installs the providers as the global ones, shutting them down again if
+    /// otex was already initialized.
+    pub(crate) fn install(
+        config: Config,
+        trace_provider: Option<sdk::trace::SdkTracerProvider>,
+        log_provider: Option<sdk::logs::SdkLoggerProvider>,
+        meter_provider: Option<sdk::metrics::SdkMeterProvider>,
+    ) -> Result<(), OtexError> {
+        // Claiming the application name decides which caller wins a race.
+        let clock = config.clock;
+        let log_filter = config.log_filter;
+        if APPLICATION_NAME.set(config.service_name).is_err() {
+            if let Some(provider) = trace_provider {
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = log_provider {
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = meter_provider {
+                let _ = provider.shutdown();
+            }
+            return Err(OtexError::AlreadyInitialized);
+        }
+
+        if let Some(clock) = clock {
+            let _ = CLOCK.set(clock);
+        }
+        if let Some(filter) = log_filter {
+            LOG_FILTER.set(filter);
+        }
+
+        // Disabled signals leave their provider unset.
+        if let Some(provider) = trace_provider {
+            TRACER_PROVIDER.set(provider).map_err(|_| OtexError::AlreadyInitialized)?;
+        }
+        if let Some(provider) = log_provider {
+            LOGGER_PROVIDER.set(provider).map_err(|_| OtexError::AlreadyInitialized)?;
+        }
+        if let Some(provider) = meter_provider {
+            METER_PROVIDER.set(provider).map_err(|_| OtexError::AlreadyInitialized)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and shuts down all providers.
+    ///
+    /// # Panics
+    /// Panics if otex is not initialized or a provider fails to shut down.
     pub fn shutdown() {
+        shutdown_providers().expect("shutdown errors");
+    }
+
+    fn shutdown_providers() -> Result<(), OtexError> {
+        APPLICATION_NAME.get().expect("application not initialized");
         opentelemetry::context::Context::current().span().end();
-        // Flush otel traces
-        let tracer_provider = TRACER_PROVIDER.get().unwrap();
-        let _ = tracer_provider.force_flush().inspect_err(|e| {
-            log::error!("{}", e)
-        });
-        tracer_provider
-            .shutdown()
-            .expect("shutdown errors");
+        let mut failed = Vec::new();
 
+        // Flush otel traces
+        if let Some(tracer_provider) = TRACER_PROVIDER.get() {
+            let _ = tracer_provider.force_flush().inspect_err(|e| {
+                log::error!("{}", e)
+            });
+            if let Err(e) = tracer_provider.shutdown() {
+                failed.push(("traces", e));
+            }
+        }
 
         // Flush otel logs
-        let logger_provider = LOGGER_PROVIDER.get().unwrap();
-        let _ = logger_provider.force_flush().inspect_err(|e| {
-            log::error!("{}", e)
-        });
-        logger_provider
-            .shutdown()
-            .expect("shutdown errors");
+        if let Some(logger_provider) = LOGGER_PROVIDER.get() {
+            let _ = logger_provider.force_flush().inspect_err(|e| {
+                log::error!("{}", e)
+            });
+            if let Err(e) = logger_provider.shutdown() {
+                failed.push(("logs", e));
+            }
+        }
+
+        // Flush log implementation
+        log::logger().flush();
+
+        // Flush otel metrics
+        if let Some(meter_provider) = METER_PROVIDER.get() {
+            let _ = meter_provider.force_flush().inspect_err(|e| {
+                log::error!("{}", e)
+            });
+            if let Err(e) = meter_provider.shutdown() {
+                failed.push(("metrics", e));
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(OtexError::Shutdown(failed))
+        }
+    }
 
+    /// Flushes and shuts down all providers, spending at most `timeout` in
+    /// total across traces, logs and metrics.
+    ///
+    /// Signals that have not finished by the deadline are reported as
+    /// [`OTelSdkError::Timeout`]; their export keeps running in the background.
+    pub fn shutdown_with_timeout(timeout: Duration) -> Result<(), OtexError> {
+        opentelemetry::context::Context::current().span().end();
 
+        let (tracer_provider, logger_provider, meter_provider) = providers();
+        let mut tasks: Vec<(&'static str, SignalTask)> = Vec::new();
+        if let Some(provider) = tracer_provider {
+            tasks.push(("traces", Box::new(move || provider.shutdown_with_timeout(timeout))));
+        }
+        if let Some(provider) = logger_provider {
+            tasks.push(("logs", Box::new(move || provider.shutdown_with_timeout(timeout))));
+        }
+        if let Some(provider) = meter_provider {
+            tasks.push(("metrics", Box::new(move || provider.shutdown_with_timeout(timeout))));
+        }
+        let failed = run_with_deadline(timeout, tasks);
 
         // Flush log implementation
         log::logger().flush();
 
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(OtexError::Shutdown(failed))
+        }
+    }
 
-        // Flush otel metrics
-        let meter_provider = METER_PROVIDER.get().unwrap();
-        let _ = meter_provider.force_flush().inspect_err(|e| {
-            log::error!("{}", e)
-        });
-        meter_provider
-            .shutdown()
-            .expect("shutdown errors");
+    /// Exports all buffered telemetry, spending at most `timeout` in total
+    /// across traces, logs and metrics. The providers stay usable afterwards.
+    pub fn flush(timeout: Duration) -> Result<(), OtexError> {
+        let (tracer_provider, logger_provider, meter_provider) = providers();
+        let mut tasks: Vec<(&'static str, SignalTask)> = Vec::new();
+        if let Some(provider) = tracer_provider {
+            tasks.push(("traces", Box::new(move || provider.force_flush())));
+        }
+        if let Some(provider) = logger_provider {
+            tasks.push(("logs", Box::new(move || provider.force_flush())));
+        }
+        if let Some(provider) = meter_provider {
+            tasks.push(("metrics", Box::new(move || provider.force_flush())));
+        }
+        let failed = run_with_deadline(timeout, tasks);
+
+        // Flush log implementation
+        log::logger().flush();
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(OtexError::Flush(failed))
+        }
+    }
 
+    pub(crate) type SignalTask = Box<dyn FnOnce() -> OTelSdkResult + Send>;
+
+    /// Clones the providers of the enabled signals.
+    fn providers() -> (
+        Option<sdk::trace::SdkTracerProvider>,
+        Option<sdk::logs::SdkLoggerProvider>,
+        Option<sdk::metrics::SdkMeterProvider>,
+    ) {
+        APPLICATION_NAME.get().expect("application not initialized");
+        (
+            TRACER_PROVIDER.get().cloned(),
+            LOGGER_PROVIDER.get().cloned(),
+            METER_PROVIDER.get().cloned(),
+        )
     }
 
+    /// Runs every task on its own thread and collects the failures, treating
+    /// tasks that do not report back before the deadline as timed out.
+    pub(crate) fn run_with_deadline(
+        timeout: Duration,
+        tasks: Vec<(&'static str, SignalTask)>,
+    ) -> Vec<(&'static str, OTelSdkError)> {
+        let deadline = Instant::now() + timeout;
+        let (sender, receiver) = mpsc::channel();
+
+        let mut pending = Vec::new();
+        for (signal, task) in tasks {
+            let sender = sender.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("otex-{}", signal))
+                .spawn(move || {
+                    let _ = sender.send((signal, task()));
+                });
+            match spawned {
+                Ok(_) => pending.push(signal),
+                Err(e) => log::error!("failed to spawn {} thread: {}", signal, e),
+            }
+        }
+        drop(sender);
+
+        let mut failed = Vec::new();
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remaining) {
+                Ok((signal, result)) => {
+                    pending.retain(|pending| *pending != signal);
+                    if let Err(e) = result {
+                        failed.push((signal, e));
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        failed.extend(
+            pending
+                .into_iter()
+                .map(|signal| (signal, OTelSdkError::Timeout(timeout))),
+        );
 
-    pub fn app_name() -> &'static str {
-        APPLICATION_NAME
+        failed
     }
 
+    /// Returns the tracer, which records nothing when traces are disabled.
+    ///
+    /// # Panics
+    /// Panics if otex is not initialized.
     pub fn tracer() -> sdk::trace::SdkTracer {
-        TRACER_PROVIDER
-            .get()
-            .expect("application not initialized")
-            .tracer(app_name())
+        enabled_tracer().unwrap_or_else(|| NOOP_TRACER_PROVIDER.tracer("otex"))
     }
 
+    /// Returns the logger, which records nothing when logs are disabled.
+    ///
+    /// # Panics
+    /// Panics if otex is not initialized.
     pub fn logger() -> sdk::logs::SdkLogger {
-        LOGGER_PROVIDER
-            .get()
-            .expect("application not initialized")
-            .logger(app_name())
+        enabled_logger().unwrap_or_else(|| NOOP_LOGGER_PROVIDER.logger("otex"))
     }
 
+    /// Returns the meter, which records nothing when metrics are disabled.
+    ///
+    /// # Panics
+    /// Panics if otex is not initialized.
     pub fn meter() -> Meter {
-        METER_PROVIDER
-            .get()
-            .expect("application not initialized")
-            .meter(app_name())
+        #[cfg(any(test, feature = "testing"))]
+        if crate::testing::is_scoped() {
+            return crate::testing::scoped_meter().unwrap_or_else(|| Meter::new(Arc::new(NoopInstruments)));
+        }
+        let name = APPLICATION_NAME.get().expect("application not initialized");
+        match METER_PROVIDER.get() {
+            Some(provider) => provider.meter(name),
+            None => Meter::new(Arc::new(NoopInstruments)),
+        }
+    }
+
+    /// Returns the tracer, or `None` if otex is not initialized or traces are disabled.
+    pub fn try_tracer() -> Option<sdk::trace::SdkTracer> {
+        #[cfg(any(test, feature = "testing"))]
+        if crate::testing::is_scoped() {
+            return crate::testing::scoped_tracer();
+        }
+        let name = APPLICATION_NAME.get()?;
+        Some(TRACER_PROVIDER.get()?.tracer(name.clone()))
+    }
+
+    /// Returns the logger, or `None` if otex is not initialized or logs are disabled.
+    pub fn try_logger() -> Option<sdk::logs::SdkLogger> {
+        #[cfg(any(test, feature = "testing"))]
+        if crate::testing::is_scoped() {
+            return crate::testing::scoped_logger();
+        }
+        let name = APPLICATION_NAME.get()?;
+        Some(LOGGER_PROVIDER.get()?.logger(name.clone()))
+    }
+
+    /// Returns the meter, or `None` if otex is not initialized or metrics are disabled.
+    pub fn try_meter() -> Option<Meter> {
+        #[cfg(any(test, feature = "testing"))]
+        if crate::testing::is_scoped() {
+            return crate::testing::scoped_meter();
+        }
+        let name = APPLICATION_NAME.get()?;
+        Some(METER_PROVIDER.get()?.meter(name))
+    }
+
+    /// Like [`try_tracer`], but panics if otex is not initialized.
+    pub(crate) fn enabled_tracer() -> Option<sdk::trace::SdkTracer> {
+        assert!(initialized(), "application not initialized");
+        try_tracer()
+    }
+
+    /// Like [`try_logger`], but panics if otex is not initialized.
+    pub(crate) fn enabled_logger() -> Option<sdk::logs::SdkLogger> {
+        assert!(initialized(), "application not initialized");
+        try_logger()
+    }
+
+    /// Returns the time of the configured clock, or the system time.
+    pub(crate) fn now() -> SystemTime {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(now) = crate::testing::scoped_config(|config| {
+            config.clock.as_ref().map_or_else(SystemTime::now, |clock| clock.now())
+        }) {
+            return now;
+        }
+        CLOCK.get().map_or_else(SystemTime::now, |clock| clock.now())
+    }
+
+    /// Whether the log filter lets a record of `severity` from `module_path`
+    /// through. Without a filter, everything passes.
+    pub fn log_enabled(severity: opentelemetry::logs::Severity, module_path: &str) -> bool {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(filter) = crate::testing::scoped_log_filter() {
+            return filter.enabled(module_path, severity);
+        }
+        LOG_FILTER.enabled(module_path, severity)
+    }
+
+    /// Whether otex is initialized, globally or for the current test.
+    fn initialized() -> bool {
+        #[cfg(any(test, feature = "testing"))]
+        if crate::testing::is_scoped() {
+            return true;
+        }
+        APPLICATION_NAME.get().is_some()
     }
+
+    /// Instrument provider backing [`meter`] when metrics are disabled.
+    struct NoopInstruments;
+
+    impl opentelemetry::metrics::InstrumentProvider for NoopInstruments {}
 }
 
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use opentelemetry_sdk::error::OTelSdkError;
+
+    use crate::init::{SignalTask, run_with_deadline};
+    use crate::{Config, OtexError};
+
+    #[test]
+    fn try_init_invalid_config() {
+        let config = Config::builder().with_endpoint("not a uri").build();
+
+        let result = crate::try_init(config);
+        assert!(matches!(result, Err(OtexError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn disabled_signals_record_nothing() {
+        use opentelemetry::logs::{LogRecord as _, Logger as _};
+        use opentelemetry::trace::{Span as _, Tracer as _};
+
+        let config = Config::builder().with_traces(false).with_logs(false).with_metrics(false).build();
+        let telemetry = crate::testing::scoped_with(config);
+
+        crate::tracer().start("request").end();
+        let logger = crate::logger();
+        let mut record = logger.create_log_record();
+        record.set_body("signed in".into());
+        logger.emit(record);
+        crate::meter().u64_counter("requests").build().add(1, &[]);
+
+        assert!(telemetry.spans().is_empty());
+        assert!(telemetry.logs().is_empty());
+        assert!(telemetry.metrics().is_empty());
+    }
+
+    #[test]
+    fn run_with_deadline_reports_failures() {
+        let tasks: Vec<(&'static str, SignalTask)> = vec![
+            ("traces", Box::new(|| Ok(()))),
+            ("logs", Box::new(|| Err(OTelSdkError::AlreadyShutdown))),
+            (
+                "metrics",
+                Box::new(|| {
+                    std::thread::sleep(Duration::from_secs(5));
+                    Ok(())
+                }),
+            ),
+        ];
+
+        let start = Instant::now();
+        let failed = run_with_deadline(Duration::from_millis(100), tasks);
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let signals = failed.iter().map(|(signal, _)| *signal).collect::<Vec<_>>();
+        assert_eq!(signals, ["logs", "metrics"]);
+        assert!(matches!(failed[1].1, OTelSdkError::Timeout(_)));
+    }
+}
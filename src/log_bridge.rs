@@ -0,0 +1,249 @@
+//! Forwards records of the `log` crate to the OpenTelemetry logger, so
+//! dependencies using `log::info!` and friends are exported like the otex
+//! log macros.
+
+use std::cell::Cell;
+
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, Severity};
+use opentelemetry::Key;
+
+thread_local! {
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` while otex passes one of its own records on to the `log` crate,
+/// which the bridge must not emit a second time.
+pub(crate) fn forwarding<R>(f: impl FnOnce() -> R) -> R {
+    /// Clears the flag even if `f` panics.
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            FORWARDING.with(|forwarding| forwarding.set(false));
+        }
+    }
+
+    FORWARDING.with(|forwarding| forwarding.set(true));
+    let _reset = Reset;
+    f()
+}
+
+/// A [`log::Log`] implementation emitting records as OpenTelemetry log
+/// records, carrying the active span's trace context.
+///
+/// ```ignore
+/// let _otex = otex::init(config);
+/// otex::LogBridge::new()
+///     .with_max_level(log::LevelFilter::Info)
+///     .with_env_logger()
+///     .install()?;
+/// log::info!(user_id = 42; "signed in");
+/// ```
+pub struct LogBridge {
+    max_level: LevelFilter,
+    inner: Option<Box<dyn Log>>,
+}
+
+impl Default for LogBridge {
+    fn default() -> Self {
+        Self {
+            max_level: LevelFilter::Trace,
+            inner: None,
+        }
+    }
+}
+
+impl LogBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only emits records up to `level` as OpenTelemetry log records.
+    pub fn with_max_level(mut self, level: LevelFilter) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Also passes every record to `logger`, e.g. for console output.
+    pub fn with_logger(mut self, logger: impl Log + 'static) -> Self {
+        self.inner = Some(Box::new(logger));
+        self
+    }
+
+    /// Also prints records with `env_logger`, configured from `RUST_LOG`.
+    pub fn with_env_logger(self) -> Self {
+        self.with_logger(env_logger::Builder::from_default_env().build())
+    }
+
+    /// Installs the bridge as the `log` crate's logger.
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        let max_level = match &self.inner {
+            Some(inner) => self.max_level.max(inner_filter(inner.as_ref())),
+            None => self.max_level,
+        };
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn emit(&self, record: &Record) {
+        let Some(logger) = crate::init::try_logger() else {
+            return;
+        };
+
+        let mut log_record = logger.create_log_record();
+        let severity = severity(record.level());
+        log_record.set_severity_number(severity);
+        log_record.set_severity_text(severity.name());
+        log_record.set_target(record.target().to_string());
+        match record.args().as_str() {
+            Some(body) => log_record.set_body(body.into()),
+            None => log_record.set_body(record.args().to_string().into()),
+        }
+
+        if let Some(module_path) = record.module_path() {
+            log_record.add_attribute("code.namespace", module_path.to_string());
+        }
+        if let Some(file) = record.file() {
+            log_record.add_attribute("code.filepath", file.to_string());
+        }
+        if let Some(line) = record.line() {
+            log_record.add_attribute("code.lineno", i64::from(line));
+        }
+        let _ = record.key_values().visit(&mut Attributes(&mut log_record));
+
+        logger.emit(log_record);
+    }
+}
+
+impl Log for LogBridge {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level
+            || self.inner.as_ref().is_some_and(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.max_level && !FORWARDING.with(Cell::get) {
+            self.emit(record);
+        }
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+/// Finds the most verbose level `logger` accepts.
+fn inner_filter(logger: &dyn Log) -> LevelFilter {
+    // Targets are not known yet, so probe with an empty one.
+    log::Level::iter()
+        .filter(|&level| logger.enabled(&Metadata::builder().level(level).target("").build()))
+        .last()
+        .map_or(LevelFilter::Off, |level| level.to_level_filter())
+}
+
+fn severity(level: log::Level) -> Severity {
+    match level {
+        log::Level::Error => Severity::Error,
+        log::Level::Warn => Severity::Warn,
+        log::Level::Info => Severity::Info,
+        log::Level::Debug => Severity::Debug,
+        log::Level::Trace => Severity::Trace,
+    }
+}
+
+/// Copies the key-values of a `log` record onto a log record.
+struct Attributes<'a, R>(&'a mut R);
+
+impl<'kvs, R: LogRecord> VisitSource<'kvs> for Attributes<'_, R> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            AnyValue::Boolean(b)
+        } else if let Some(i) = value.to_i64() {
+            AnyValue::Int(i)
+        } else if let Some(f) = value.to_f64() {
+            AnyValue::Double(f)
+        } else if let Some(s) = value.to_borrowed_str() {
+            AnyValue::from(s.to_string())
+        } else {
+            AnyValue::from(value.to_string())
+        };
+        self.0.add_attribute(Key::new(key.as_str().to_string()), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, LevelFilter, Log, Record};
+    use opentelemetry::trace::TraceContextExt;
+
+    use super::LogBridge;
+
+    fn record(level: Level, f: impl FnOnce(&Record)) {
+        let kvs: [(&str, log::kv::Value); 2] = [("user_id", 42.into()), ("admin", false.into())];
+        f(&Record::builder()
+            .level(level)
+            .target("billing::db")
+            .module_path(Some("billing::db"))
+            .file(Some("src/db.rs"))
+            .line(Some(12))
+            .args(format_args!("query took {}ms", 5))
+            .key_values(&kvs)
+            .build());
+    }
+
+    #[test]
+    fn bridges_log_records() {
+        let telemetry = crate::testing::scoped();
+        let bridge = LogBridge::new().with_max_level(LevelFilter::Info);
+
+        let span = crate::context!("request");
+        let trace_id = span.span().span_context().trace_id();
+        {
+            let _span = span.attach();
+            record(Level::Warn, |record| bridge.log(record));
+            record(Level::Debug, |record| bridge.log(record));
+        }
+
+        crate::assert_log!(severity = Warn, body = "query took 5ms")
+            .has_attr("user_id", 42)
+            .has_attr("admin", false)
+            .has_attr("code.filepath", "src/db.rs")
+            .has_attr("code.lineno", 12)
+            .in_span(&telemetry, "request");
+        let logs = telemetry.logs();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].target().map(|target| target.as_ref()), Some("billing::db"));
+        assert_eq!(logs[0].trace_context().unwrap().trace_id, trace_id);
+    }
+
+    #[test]
+    fn skips_records_forwarded_by_otex() {
+        let telemetry = crate::testing::scoped();
+        let bridge = LogBridge::new();
+
+        super::forwarding(|| record(Level::Error, |record| bridge.log(record)));
+
+        assert!(telemetry.logs().is_empty());
+    }
+
+    #[test]
+    fn panicking_logger_resets_forwarding() {
+        let telemetry = crate::testing::scoped();
+        let bridge = LogBridge::new();
+
+        let panicked = std::panic::catch_unwind(|| super::forwarding(|| panic!("logger failed")));
+        assert!(panicked.is_err());
+        record(Level::Error, |record| bridge.log(record));
+
+        assert_eq!(telemetry.logs().len(), 1);
+    }
+}
@@ -1,35 +1,77 @@
-use std::{env, fmt::Arguments, panic::Location};
+use std::{fmt::Arguments, panic::Location};
 
 use opentelemetry::logs::{LogRecord, Logger};
+use opentelemetry::trace::{SpanContext, TraceContextExt};
 use opentelemetry_sdk::{self as sdk};
 
-pub(crate) fn init_logging() -> sdk::logs::SdkLoggerProvider {
-    let mut builder = sdk::logs::LoggerProviderBuilder::default();
+use crate::config::{BatchConfig, Config, Exporter};
+use crate::error::OtexError;
 
-    let export_enabled: bool = std::env::var("OTEX_EXPORT")
-        .map(|s| s.to_lowercase())
-        .unwrap_or("true".to_string())
-        .parse()
-        .unwrap_or(true);
-
-    if export_enabled {
-        let exporter = opentelemetry_otlp::LogExporterBuilder::default()
-            .with_http()
-            .build()
-            .expect("failed to build exporter");
+pub(crate) fn init_logging(
+    config: &Config,
+    resource: &sdk::Resource,
+) -> Result<Option<sdk::logs::SdkLoggerProvider>, OtexError> {
+    let signal = &config.logs;
+    if !signal.enabled {
+        return Ok(None);
+    }
 
-        builder = builder.with_batch_exporter(exporter);
+    let mut builder = sdk::logs::LoggerProviderBuilder::default()
+        .with_resource(resource.clone());
+    // Runs before the exporting processor, which sees the clock's timestamps.
+    if let Some(clock) = &config.clock {
+        builder = builder.with_log_processor(crate::clock::ClockLogProcessor::new(clock.clone()));
     }
 
-    #[cfg(feature = "stdout")]
-    {
-        let stdout_exporter = opentelemetry_stdout::LogExporter::default();
-        builder = builder.with_batch_exporter(stdout_exporter);
+    match signal.exporter {
+        Exporter::Otlp => {
+            let exporter = crate::otlp::log_exporter(config)?;
+            builder = builder.with_log_processor(batch_processor(config, exporter));
+        }
+        Exporter::Console => {
+            let console_exporter = crate::console::ConsoleLogExporter::new();
+            builder = builder.with_log_processor(batch_processor(config, console_exporter));
+        }
+        #[cfg(feature = "stdout")]
+        Exporter::Stdout => {
+            let stdout_exporter = opentelemetry_stdout::LogExporter::default();
+            builder = builder.with_log_processor(batch_processor(config, stdout_exporter));
+        }
+        #[cfg(feature = "file")]
+        Exporter::File => {
+            let file_exporter = crate::file::FileLogExporter::new(crate::file::sink(config)?);
+            builder = builder.with_log_processor(batch_processor(config, file_exporter));
+        }
+        Exporter::None => {}
     }
 
+    Ok(Some(builder.build()))
+}
+
+fn batch_processor(
+    config: &Config,
+    exporter: impl sdk::logs::LogExporter + 'static,
+) -> sdk::logs::BatchLogProcessor {
+    sdk::logs::BatchLogProcessor::builder(exporter)
+        .with_batch_config(batch_config(&config.logs.batch))
+        .build()
+}
+
+fn batch_config(batch: &BatchConfig) -> sdk::logs::BatchConfig {
+    let mut builder = sdk::logs::BatchConfigBuilder::default();
+    if let Some(size) = batch.max_queue_size {
+        builder = builder.with_max_queue_size(size);
+    }
+    if let Some(size) = batch.max_export_batch_size {
+        builder = builder.with_max_export_batch_size(size);
+    }
+    if let Some(delay) = batch.scheduled_delay {
+        builder = builder.with_scheduled_delay(delay);
+    }
     builder.build()
 }
 
+/// Emits a log record, unless the module filter drops it.
 pub fn create_log_record(
     severity: opentelemetry::logs::Severity,
     module_path: &'static str,
@@ -37,26 +79,66 @@ pub fn create_log_record(
     body: Option<opentelemetry::logs::AnyValue>,
     attributes: &[(opentelemetry::Key, opentelemetry::logs::AnyValue)],
 ) {
-    let logger = crate::init::logger();
+    if crate::init::log_enabled(severity, module_path) {
+        log_record(log::logger(), severity, module_path, name, body, attributes);
+    }
+}
 
+/// [`create_log_record`] without the module filter, for the [`crate::log!`]
+/// macro, which checks it before evaluating the body and attributes.
+#[doc(hidden)]
+pub fn create_log_record_unfiltered(
+    severity: opentelemetry::logs::Severity,
+    module_path: &'static str,
+    name: Option<&'static str>,
+    body: Option<opentelemetry::logs::AnyValue>,
+    attributes: &[(opentelemetry::Key, opentelemetry::logs::AnyValue)],
+) {
+    log_record(log::logger(), severity, module_path, name, body, attributes);
+}
+
+/// Emits the OpenTelemetry record when logs are enabled, and passes the
+/// record on to `forward` either way, so console output keeps working with
+/// logs disabled.
+fn log_record(
+    forward: &dyn log::Log,
+    severity: opentelemetry::logs::Severity,
+    module_path: &'static str,
+    name: Option<&'static str>,
+    body: Option<opentelemetry::logs::AnyValue>,
+    attributes: &[(opentelemetry::Key, opentelemetry::logs::AnyValue)],
+) {
     let location = Location::caller();
-    let mut record = logger.create_log_record();
+    let context = opentelemetry::Context::current();
+    let span_context = context.span().span_context().clone();
 
-    if let Some(name) = name {
-        record.set_event_name(name);
-    }
+    if let Some(logger) = crate::init::enabled_logger() {
+        let mut record = logger.create_log_record();
 
-    if let Some(body) = &body {
-        record.set_body(body.clone());
-    }
+        if let Some(name) = name {
+            record.set_event_name(name);
+        }
 
-    record.add_attributes(attributes.to_owned());
+        if let Some(body) = &body {
+            record.set_body(body.clone());
+        }
 
-    record.set_severity_number(severity);
-    record.set_severity_text(severity.name());
+        record.add_attributes(attributes.to_owned());
 
-    // Emit otel record
-    logger.emit(record);
+        record.set_severity_number(severity);
+        record.set_severity_text(severity.name());
+
+        if span_context.is_valid() {
+            record.set_trace_context(
+                span_context.trace_id(),
+                span_context.span_id(),
+                Some(span_context.trace_flags()),
+            );
+        }
+
+        // Emit otel record
+        logger.emit(record);
+    }
 
     // Emit log impl record
     let log_attributes = attributes
@@ -82,9 +164,11 @@ pub fn create_log_record(
             _ => todo!(),
         };
         emit_log_impl_record(
+            forward,
             severity,
             module_path,
             location,
+            &span_context,
             &log_attributes,
             &format_args!("{}", formatted_body),
         );
@@ -92,9 +176,11 @@ pub fn create_log_record(
 }
 
 fn emit_log_impl_record<'a>(
+    logger: &dyn log::Log,
     severity: opentelemetry::logs::Severity,
     module_path: &'static str,
     location: &'a Location<'a>,
+    span_context: &SpanContext,
     attributes: &'a [(log::kv::Key, log::kv::Value)],
     arguments: &'a Arguments<'a>,
 ) {
@@ -105,7 +191,19 @@ fn emit_log_impl_record<'a>(
         .file(Some(location.file()))
         .line(Some(location.line()));
 
-    log_builder.key_values(&attributes);
+    // Lets console output be matched to the trace it was logged in.
+    let trace_ids = span_context.is_valid().then(|| {
+        [
+            ("trace_id", span_context.trace_id().to_string()),
+            ("span_id", span_context.span_id().to_string()),
+            ("trace_flags", format!("{:02x}", span_context.trace_flags().to_u8())),
+        ]
+    });
+    let mut key_values = attributes.to_vec();
+    for (key, value) in trace_ids.iter().flatten() {
+        key_values.push((log::kv::Key::from_str(key), log::kv::Value::from(value.as_str())));
+    }
+    log_builder.key_values(&key_values);
 
     use opentelemetry::logs::Severity;
     let log_level = match severity {
@@ -129,22 +227,29 @@ fn emit_log_impl_record<'a>(
     log_builder.args(*arguments);
 
     let log_record = log_builder.build();
-    log::logger().log(&log_record);
+    crate::log_bridge::forwarding(|| logger.log(&log_record));
 }
 
 #[cfg(test)]
 mod test {
+    use std::panic::Location;
+    use std::sync::Mutex;
+
+    use opentelemetry::logs::{AnyValue, Severity};
+    use opentelemetry::trace::{SpanContext, TraceContextExt};
 
     #[test]
     fn test_log_provider() {
         let logger = env_logger::Builder::from_default_env().build();
         log::set_max_level(logger.filter());
 
-        log::set_boxed_logger(Box::new(logger));
+        let _ = log::set_boxed_logger(Box::new(logger));
 
-        let otex = crate::init();
+        let _telemetry = crate::testing::scoped();
 
-        let span = crate::context!("test", value = "attach").attach();
+        let span = crate::context!("test", value = "attach");
+        let trace_id = span.span().span_context().trace_id();
+        let _span = span.attach();
 
         crate::event!("test", attr = "name");
         crate::log!(
@@ -154,23 +259,147 @@ mod test {
             test_key = "hello"
         );
 
-        otex.shutdown();
+        let log = crate::assert_log!(severity = Error, body = "error!", name = "test log")
+            .has_attr("test_key", "hello");
+        assert_eq!(log.record().trace_context().unwrap().trace_id, trace_id);
+    }
+
+    /// Keeps the key-values of the records it is given.
+    #[derive(Default)]
+    struct Capture(Mutex<Vec<Vec<(String, String)>>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let mut key_values = KeyValues(Vec::new());
+            let _ = record.key_values().visit(&mut key_values);
+            self.0.lock().unwrap().push(key_values.0);
+        }
+
+        fn flush(&self) {}
+    }
+
+    struct KeyValues(Vec<(String, String)>);
+
+    impl<'kvs> log::kv::VisitSource<'kvs> for KeyValues {
+        fn visit_pair(
+            &mut self,
+            key: log::kv::Key<'kvs>,
+            value: log::kv::Value<'kvs>,
+        ) -> Result<(), log::kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn forwarded_with_logs_disabled() {
+        let config = crate::Config::builder().with_logs(false).build();
+        let telemetry = crate::testing::scoped_with(config);
+        let capture = Capture::default();
+
+        let attributes = [(opentelemetry::Key::new("user_id"), AnyValue::Int(42))];
+        super::log_record(&capture, Severity::Info, module_path!(), None, Some("signed in".into()), &attributes);
+
+        assert!(telemetry.logs().is_empty());
+        let captured = capture.0.lock().unwrap();
+        assert_eq!(captured.as_slice(), [vec![("user_id".to_string(), "Int(42)".to_string())]]);
+    }
+
+    #[test]
+    fn trace_context_on_log_records() {
+        let _telemetry = crate::testing::scoped();
+        let capture = Capture::default();
+        let emit = |span_context: &SpanContext| {
+            let attributes = [(log::kv::Key::from_str("user_id"), log::kv::Value::from(42))];
+            super::emit_log_impl_record(
+                &capture,
+                Severity::Info,
+                module_path!(),
+                Location::caller(),
+                span_context,
+                &attributes,
+                &format_args!("signed in"),
+            );
+        };
+
+        let span = crate::context!("request");
+        let span_context = span.span().span_context().clone();
+        {
+            let _span = span.attach();
+            crate::info_log!("signed in");
+        }
+        emit(&span_context);
+        emit(&SpanContext::empty_context());
+
+        let log = crate::assert_log!(body = "signed in");
+        let trace_context = log.record().trace_context().unwrap();
+        assert_eq!(trace_context.span_id, span_context.span_id());
+        assert_eq!(trace_context.trace_flags, Some(span_context.trace_flags()));
+
+        let captured = capture.0.lock().unwrap();
+        let expected = |key: &str, value: String| (key.to_string(), value);
+        assert_eq!(
+            captured[0],
+            [
+                expected("user_id", "42".to_string()),
+                expected("trace_id", span_context.trace_id().to_string()),
+                expected("span_id", span_context.span_id().to_string()),
+                expected("trace_flags", "01".to_string()),
+            ]
+        );
+        assert_eq!(captured[1], [expected("user_id", "42".to_string())]);
     }
 
     #[test]
     fn test_info() {
         let logger = env_logger::Builder::from_default_env().build();
         log::set_max_level(logger.filter());
-        log::set_boxed_logger(Box::new(logger));
+        let _ = log::set_boxed_logger(Box::new(logger));
+
+        let telemetry = crate::testing::scoped();
 
-        let otex = crate::init();
+        crate::info_log!("test log", "info test!");
 
-        crate::info_log!("test log", "test!");
+        crate::info_log!("test log", "info test!", key = 1);
 
-        crate::info_log!("test log", "test!", key = 1);
+        crate::info_log!("info test!");
 
-        crate::info_log!("test!");
+        let records: Vec<_> = telemetry
+            .logs_with_severity(Severity::Info)
+            .into_iter()
+            .filter(|record| record.body() == Some(&AnyValue::from("info test!")))
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].event_name(), Some("test log"));
+        assert_eq!(records[2].event_name(), None);
+        assert!(records[1].attributes_iter().any(|(key, _)| key.as_str() == "key"));
+    }
 
-        otex.shutdown();
+    #[test]
+    fn filtered_by_module() {
+        let config = crate::Config::builder()
+            .with_log_filter("error,otex::logger=info".parse().unwrap())
+            .build();
+        let telemetry = crate::testing::scoped_with(config);
+
+        let mut evaluated = 0;
+        crate::info_log!("kept");
+        crate::log!(None, Severity::Debug, {
+            evaluated += 1;
+            "dropped"
+        });
+
+        assert_eq!(evaluated, 0);
+        let bodies: Vec<_> = telemetry.logs().into_iter().filter_map(|record| record.body().cloned()).collect();
+        assert_eq!(bodies, [AnyValue::from("kept")]);
+        assert!(!crate::log_enabled(Severity::Warn, "hyper::client"));
+
+        // Calling the function directly still applies the filter.
+        crate::create_log_record(Severity::Debug, module_path!(), None, Some("direct".into()), &[]);
+        assert_eq!(telemetry.logs().len(), 1);
     }
 }
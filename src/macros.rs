@@ -55,21 +55,32 @@ macro_rules! anykvset {
 // LOGGING
 #[macro_export]
 macro_rules! log {
+    // Filtered records skip evaluating the body and attributes.
+
     // No attributes
     ($name:expr, $severity:expr, $body:expr) => {{
-        $crate::create_log_record($severity, module_path!(), $name, Some($body.into()), &[])
+        let severity = $severity;
+        if $crate::log_enabled(severity, module_path!()) {
+            $crate::create_log_record_unfiltered(severity, module_path!(), $name, Some($body.into()), &[])
+        }
     }};
 
     // key = value form
     ($name:expr, $severity:expr, $body:expr, $( $attr_key:tt = $attr_value:expr ),+ $(,)?) => {{
-        let attrs = $crate::anykvset!($( $attr_key = $attr_value ),*);
-        $crate::create_log_record($severity, module_path!(), $name, Some($body.into()), &attrs)
+        let severity = $severity;
+        if $crate::log_enabled(severity, module_path!()) {
+            let attrs = $crate::anykvset!($( $attr_key = $attr_value ),*);
+            $crate::create_log_record_unfiltered(severity, module_path!(), $name, Some($body.into()), &attrs)
+        }
     }};
 
     // shorthand: ident only
     ($name:expr, $severity:expr, $body:expr, $( $attr:ident ),+ $(,)?) => {{
-        let attrs = $crate::anykvset!($( $attr ),*);
-        $crate::create_log_record($severity, module_path!(), $name, Some($body.into()), &attrs)
+        let severity = $severity;
+        if $crate::log_enabled(severity, module_path!()) {
+            let attrs = $crate::anykvset!($( $attr ),*);
+            $crate::create_log_record_unfiltered(severity, module_path!(), $name, Some($body.into()), &attrs)
+        }
     }};
 }
 #[macro_export]
@@ -264,19 +275,19 @@ macro_rules! event {
 macro_rules! error_event {
     // No attributes
     ($name:expr, $desc:expr) => {{
-        $crate::new_error_event($name, $desc &[])
+        $crate::new_error_event($name, $desc, &[])
     }};
 
     // key = value form
     ($name:expr, $desc:expr, $( $attr_key:tt = $attr_value:expr ),+ $(,)?) => {{
         let attrs = $crate::kvset!($( $attr_key = $attr_value ),*);
-        $crate::new_error_event($name, &attrs)
+        $crate::new_error_event($name, $desc, &attrs)
     }};
 
     // shorthand: ident only
     ($name:expr, $desc:expr, $( $attr:ident ),+ $(,)?) => {{
         let attrs = $crate::kvset!($( $attr ),*);
-        $crate::new_error_event($name, &attrs)
+        $crate::new_error_event($name, $desc, &attrs)
     }};
 }
 
@@ -707,8 +718,8 @@ mod tests {
         
         // Test macros with array/vector attributes
         let _test4 = || {
-            let tags = vec!["important", "urgent"];
-            warn_log!("tagged_warning", "system warning", tag_count = tags.len() as i64, first_tag = *tags.get(0).unwrap_or(&"none"));
+            let tags = ["important", "urgent"];
+            warn_log!("tagged_warning", "system warning", tag_count = tags.len() as i64, first_tag = *tags.first().unwrap_or(&"none"));
         };
         
         // Test macros with nested macro calls
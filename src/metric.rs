@@ -1,37 +1,62 @@
 use opentelemetry_sdk::{self as sdk};
 
-pub(crate) fn init_metrics() -> sdk::metrics::SdkMeterProvider {
-    let mut builder = sdk::metrics::MeterProviderBuilder::default();
+use crate::config::{Config, Exporter};
+use crate::error::OtexError;
 
-    let export_enabled: bool = std::env::var("OTEX_EXPORT")
-        .map(|s| s.to_lowercase())
-        .unwrap_or("true".to_string())
-        .parse()
-        .unwrap_or(true);
+pub(crate) fn init_metrics(
+    config: &Config,
+    resource: &sdk::Resource,
+) -> Result<Option<sdk::metrics::SdkMeterProvider>, OtexError> {
+    let signal = &config.metrics;
+    if !signal.enabled {
+        return Ok(None);
+    }
 
-    if export_enabled {
-        let exporter = opentelemetry_otlp::MetricExporterBuilder::new()
-            .with_http()
-            .build()
-            .expect("failed to build exporter");
+    let mut builder = sdk::metrics::MeterProviderBuilder::default()
+        .with_resource(resource.clone());
 
-        builder = builder.with_periodic_exporter(exporter);
+    match signal.exporter {
+        Exporter::Otlp => {
+            let exporter = crate::otlp::metric_exporter(config)?;
+            builder = builder.with_reader(periodic_reader(config, exporter));
+        }
+        Exporter::Console => {
+            let console_exporter = crate::console::ConsoleMetricExporter::new();
+            builder = builder.with_reader(periodic_reader(config, console_exporter));
+        }
+        #[cfg(feature = "stdout")]
+        Exporter::Stdout => {
+            let stdout_exporter = opentelemetry_stdout::MetricExporter::default();
+            builder = builder.with_reader(periodic_reader(config, stdout_exporter));
+        }
+        #[cfg(feature = "file")]
+        Exporter::File => {
+            let file_exporter = crate::file::FileMetricExporter::new(crate::file::sink(config)?);
+            builder = builder.with_reader(periodic_reader(config, file_exporter));
+        }
+        Exporter::None => {}
     }
 
-    #[cfg(feature = "stdout")]
-    {
-        let stdout_exporter = opentelemetry_stdout::MetricExporter::default();
-        builder = builder.with_periodic_exporter(stdout_exporter);
-    }
+    Ok(Some(builder.build()))
+}
 
-    builder.build()
+fn periodic_reader<E: sdk::metrics::exporter::PushMetricExporter>(
+    config: &Config,
+    exporter: E,
+) -> sdk::metrics::PeriodicReader<E> {
+    let mut reader = sdk::metrics::PeriodicReader::builder(exporter);
+    if let Some(interval) = config.metrics.export_interval {
+        reader = reader.with_interval(interval);
+    }
+    reader.build()
 }
 
 #[cfg(test)]
 mod tests {
+
     #[test]
     fn test_meter() {
-        let otex = crate::init();
+        let _telemetry = crate::testing::scoped();
 
         {
             let meter = crate::init::meter();
@@ -62,6 +87,16 @@ mod tests {
             counter.add(1, &[]);
         }
 
-        otex.shutdown()
+        let metric = crate::assert_metric!("test").sum(2);
+        assert!(metric.points().iter().all(|point| point.unit == "mb"));
+        metric.with_attr("testas", 100).count(1).histogram_sum(5);
+    }
+
+    #[test]
+    fn disabled_builds_no_provider() {
+        let config = crate::Config::builder().with_metrics(false).build();
+        let resource = opentelemetry_sdk::Resource::builder_empty().build();
+
+        assert!(super::init_metrics(&config, &resource).unwrap().is_none());
     }
 }
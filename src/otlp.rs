@@ -0,0 +1,434 @@
+//! OTLP exporters for the transport selected by [`Protocol`].
+//!
+//! Each transport is compiled in only when its cargo feature is enabled, as
+//! are gzip compression and custom TLS roots or client certificates: `gzip`
+//! and `tls` for HTTP, `grpc-gzip` and `grpc-tls` for gRPC, so HTTP-only
+//! builds do not pull in tonic. Asking for one whose feature is missing fails
+//! in [`crate::try_init`] with [`OtexError::InvalidConfig`].
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+use opentelemetry_otlp::{LogExporter, MetricExporter, SpanExporter, WithExportConfig};
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+use crate::config::{Config, OtlpConfig};
+use crate::config::Protocol;
+use crate::error::OtexError;
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+pub(crate) fn span_exporter(config: &Config) -> Result<impl opentelemetry_sdk::trace::SpanExporter + use<>, OtexError> {
+    let signal = &config.traces;
+    let endpoint = config.signal_endpoint(signal, "/v1/traces");
+
+    let exporter = match signal.protocol {
+        #[cfg(feature = "grpc")]
+        Protocol::Grpc => tonic(SpanExporter::builder().with_tonic(), config, endpoint)?.build(),
+        #[cfg(feature = "http-proto")]
+        Protocol::HttpProtobuf => http(SpanExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[cfg(feature = "http-json")]
+        Protocol::HttpJson => http(SpanExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[allow(unreachable_patterns)]
+        protocol => return Err(unsupported_protocol("traces", protocol)),
+    };
+    let exporter = exporter.map_err(|source| OtexError::ExporterBuild { signal: "traces", source })?;
+    #[cfg(feature = "retry-queue")]
+    let exporter = {
+        let spool = crate::retry::Spool::open(config, "traces", signal, "/v1/traces")?;
+        crate::retry::RetrySpanExporter::new(exporter, spool)
+    };
+    #[cfg(not(feature = "retry-queue"))]
+    check_retry_queue(config)?;
+    Ok(exporter)
+}
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+pub(crate) fn log_exporter(config: &Config) -> Result<impl opentelemetry_sdk::logs::LogExporter + use<>, OtexError> {
+    let signal = &config.logs;
+    let endpoint = config.signal_endpoint(signal, "/v1/logs");
+
+    let exporter = match signal.protocol {
+        #[cfg(feature = "grpc")]
+        Protocol::Grpc => tonic(LogExporter::builder().with_tonic(), config, endpoint)?.build(),
+        #[cfg(feature = "http-proto")]
+        Protocol::HttpProtobuf => http(LogExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[cfg(feature = "http-json")]
+        Protocol::HttpJson => http(LogExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[allow(unreachable_patterns)]
+        protocol => return Err(unsupported_protocol("logs", protocol)),
+    };
+    let exporter = exporter.map_err(|source| OtexError::ExporterBuild { signal: "logs", source })?;
+    #[cfg(feature = "retry-queue")]
+    let exporter = {
+        let spool = crate::retry::Spool::open(config, "logs", signal, "/v1/logs")?;
+        crate::retry::RetryLogExporter::new(exporter, spool)
+    };
+    #[cfg(not(feature = "retry-queue"))]
+    check_retry_queue(config)?;
+    Ok(exporter)
+}
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+pub(crate) fn metric_exporter(config: &Config) -> Result<impl opentelemetry_sdk::metrics::exporter::PushMetricExporter + use<>, OtexError> {
+    let signal = &config.metrics;
+    let endpoint = config.signal_endpoint(signal, "/v1/metrics");
+
+    let exporter = match signal.protocol {
+        #[cfg(feature = "grpc")]
+        Protocol::Grpc => tonic(MetricExporter::builder().with_tonic(), config, endpoint)?.build(),
+        #[cfg(feature = "http-proto")]
+        Protocol::HttpProtobuf => http(MetricExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[cfg(feature = "http-json")]
+        Protocol::HttpJson => http(MetricExporter::builder().with_http(), config, signal.protocol, endpoint)?.build(),
+        #[allow(unreachable_patterns)]
+        protocol => return Err(unsupported_protocol("metrics", protocol)),
+    };
+    let exporter = exporter.map_err(|source| OtexError::ExporterBuild { signal: "metrics", source })?;
+    #[cfg(feature = "retry-queue")]
+    let exporter = {
+        let spool = crate::retry::Spool::open(config, "metrics", signal, "/v1/metrics")?;
+        crate::retry::RetryMetricExporter::new(exporter, spool)
+    };
+    #[cfg(not(feature = "retry-queue"))]
+    check_retry_queue(config)?;
+    Ok(exporter)
+}
+
+#[cfg(any(feature = "http-proto", feature = "http-json"))]
+fn http<B: WithExportConfig + opentelemetry_otlp::WithHttpConfig>(
+    builder: B,
+    config: &Config,
+    protocol: Protocol,
+    endpoint: Option<String>,
+) -> Result<B, OtexError> {
+    let protocol = match protocol {
+        Protocol::HttpJson => opentelemetry_otlp::Protocol::HttpJson,
+        _ => opentelemetry_otlp::Protocol::HttpBinary,
+    };
+    let otlp = &config.otlp;
+
+    let mut builder = builder
+        .with_protocol(protocol)
+        .with_headers(otlp.headers.iter().cloned().collect());
+    if let Some(endpoint) = endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    if let Some(timeout) = otlp.timeout {
+        builder = builder.with_timeout(timeout);
+    }
+    // The default client covers everything but compression and custom TLS.
+    if otlp.gzip || has_tls(otlp) {
+        builder = builder.with_http_client(http_client::HttpClient::new(otlp)?);
+    }
+    Ok(builder)
+}
+
+#[cfg(feature = "grpc")]
+fn tonic<B: WithExportConfig + opentelemetry_otlp::WithTonicConfig>(
+    builder: B,
+    config: &Config,
+    endpoint: Option<String>,
+) -> Result<B, OtexError> {
+    use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+
+    let otlp = &config.otlp;
+    // Headers are checked by `Config::validate`.
+    let headers = otlp.headers.iter().filter_map(|(name, value)| {
+        let name = http::HeaderName::from_bytes(name.as_bytes()).ok()?;
+        Some((name, http::HeaderValue::from_str(value).ok()?))
+    });
+    let metadata = MetadataMap::from_headers(headers.collect());
+
+    let mut builder = builder
+        .with_protocol(opentelemetry_otlp::Protocol::Grpc)
+        .with_metadata(metadata);
+    if let Some(endpoint) = endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    if let Some(timeout) = otlp.timeout {
+        builder = builder.with_timeout(timeout);
+    }
+    if otlp.gzip {
+        #[cfg(feature = "grpc-gzip")]
+        {
+            builder = builder.with_compression(opentelemetry_otlp::Compression::Gzip);
+        }
+        #[cfg(not(feature = "grpc-gzip"))]
+        return Err(feature_required("gzip compression", "grpc-gzip"));
+    }
+    if has_tls(otlp) {
+        #[cfg(feature = "grpc-tls")]
+        {
+            builder = builder.with_tls_config(tonic_tls_config(otlp)?);
+        }
+        #[cfg(not(feature = "grpc-tls"))]
+        return Err(feature_required("TLS configuration", "grpc-tls"));
+    }
+    Ok(builder)
+}
+
+#[cfg(feature = "grpc-tls")]
+fn tonic_tls_config(
+    otlp: &OtlpConfig,
+) -> Result<opentelemetry_otlp::tonic_types::transport::ClientTlsConfig, OtexError> {
+    use opentelemetry_otlp::tonic_types::transport::{Certificate, ClientTlsConfig, Identity};
+
+    let mut tls = ClientTlsConfig::new().with_native_roots();
+    if let Some(path) = &otlp.ca_certificate {
+        tls = tls.ca_certificate(Certificate::from_pem(read_pem(path)?));
+    }
+    if let (Some(certificate), Some(key)) = (&otlp.client_certificate, &otlp.client_key) {
+        tls = tls.identity(Identity::from_pem(read_pem(certificate)?, read_pem(key)?));
+    }
+    Ok(tls)
+}
+
+#[cfg(any(feature = "grpc", feature = "http-proto", feature = "http-json"))]
+fn has_tls(otlp: &OtlpConfig) -> bool {
+    otlp.ca_certificate.is_some() || otlp.client_certificate.is_some()
+}
+
+#[cfg(any(feature = "grpc-tls", all(feature = "tls", any(feature = "http-proto", feature = "http-json"))))]
+fn read_pem(path: &std::path::Path) -> Result<Vec<u8>, OtexError> {
+    std::fs::read(path).map_err(|err| {
+        OtexError::InvalidConfig(format!("failed to read {}: {}", path.display(), err))
+    })
+}
+
+#[cfg(all(
+    any(feature = "grpc", feature = "http-proto", feature = "http-json"),
+    not(feature = "retry-queue")
+))]
+fn check_retry_queue(config: &Config) -> Result<(), OtexError> {
+    match config.retry_queue {
+        Some(_) => Err(feature_required("the retry queue", "retry-queue")),
+        None => Ok(()),
+    }
+}
+
+/// Without a transport feature, OTLP export fails for every protocol.
+#[cfg(not(any(feature = "grpc", feature = "http-proto", feature = "http-json")))]
+mod no_transport {
+    use std::time::Duration;
+
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::logs::LogBatch;
+    use opentelemetry_sdk::metrics::Temporality;
+    use opentelemetry_sdk::metrics::data::ResourceMetrics;
+    use opentelemetry_sdk::trace::SpanData;
+
+    use super::unsupported_protocol;
+    use crate::config::Config;
+    use crate::error::OtexError;
+
+    /// Stands in for the exporter type; it has no values.
+    #[derive(Debug)]
+    pub(crate) enum NoTransport {}
+
+    pub(crate) fn span_exporter(config: &Config) -> Result<NoTransport, OtexError> {
+        Err(unsupported_protocol("traces", config.traces.protocol))
+    }
+
+    pub(crate) fn log_exporter(config: &Config) -> Result<NoTransport, OtexError> {
+        Err(unsupported_protocol("logs", config.logs.protocol))
+    }
+
+    pub(crate) fn metric_exporter(config: &Config) -> Result<NoTransport, OtexError> {
+        Err(unsupported_protocol("metrics", config.metrics.protocol))
+    }
+
+    impl opentelemetry_sdk::trace::SpanExporter for NoTransport {
+        async fn export(&self, _batch: Vec<SpanData>) -> OTelSdkResult {
+            match *self {}
+        }
+    }
+
+    impl opentelemetry_sdk::logs::LogExporter for NoTransport {
+        async fn export(&self, _batch: LogBatch<'_>) -> OTelSdkResult {
+            match *self {}
+        }
+    }
+
+    impl opentelemetry_sdk::metrics::exporter::PushMetricExporter for NoTransport {
+        async fn export(&self, _metrics: &ResourceMetrics) -> OTelSdkResult {
+            match *self {}
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            match *self {}
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            match *self {}
+        }
+
+        fn temporality(&self) -> Temporality {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(not(any(feature = "grpc", feature = "http-proto", feature = "http-json")))]
+pub(crate) use no_transport::{log_exporter, metric_exporter, span_exporter};
+
+fn unsupported_protocol(signal: &str, protocol: Protocol) -> OtexError {
+    let feature = match protocol {
+        Protocol::Grpc => "grpc",
+        Protocol::HttpProtobuf => "http-proto",
+        Protocol::HttpJson => "http-json",
+    };
+    feature_required(&format!("{} protocol {:?}", signal, protocol), feature)
+}
+
+fn feature_required(what: &str, feature: &str) -> OtexError {
+    OtexError::InvalidConfig(format!("{} requires the `{}` feature", what, feature))
+}
+
+/// A blocking reqwest client that can gzip request bodies and use custom TLS roots.
+#[cfg(any(feature = "http-proto", feature = "http-json"))]
+pub(crate) mod http_client {
+    use std::time::Duration;
+
+    use opentelemetry_http::{Bytes, HttpError, Request, Response};
+
+    use super::OtlpConfig;
+    use crate::error::OtexError;
+
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[derive(Debug)]
+    pub(crate) struct HttpClient {
+        client: reqwest::blocking::Client,
+        #[cfg(feature = "gzip")]
+        gzip: bool,
+    }
+
+    impl HttpClient {
+        pub(crate) fn new(otlp: &OtlpConfig) -> Result<Self, OtexError> {
+            #[cfg(not(feature = "gzip"))]
+            if otlp.gzip {
+                return Err(super::feature_required("gzip compression", "gzip"));
+            }
+
+            let builder = reqwest::blocking::Client::builder()
+                .timeout(otlp.timeout.unwrap_or(DEFAULT_TIMEOUT));
+            let builder = with_tls(builder, otlp)?;
+
+            // The blocking client owns a runtime, which cannot be created
+            // from within an async context.
+            let client = std::thread::spawn(move || builder.build())
+                .join()
+                .map_err(|_| OtexError::InvalidConfig("failed to build the HTTP client".to_string()))?
+                .map_err(|err| OtexError::InvalidConfig(format!("failed to build the HTTP client: {}", err)))?;
+
+            Ok(Self {
+                client,
+                #[cfg(feature = "gzip")]
+                gzip: otlp.gzip,
+            })
+        }
+
+        /// Sends `request` on the calling thread and returns the response status.
+        #[cfg(feature = "retry-queue")]
+        pub(crate) fn send(&self, request: Request<Bytes>) -> Result<http::StatusCode, HttpError> {
+            #[cfg(feature = "gzip")]
+            let request = if self.gzip { gzip(request)? } else { request };
+
+            let request = reqwest::blocking::Request::try_from(request.map(|body| body.to_vec()))?;
+            Ok(self.client.execute(request)?.status())
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    fn with_tls(
+        mut builder: reqwest::blocking::ClientBuilder,
+        otlp: &OtlpConfig,
+    ) -> Result<reqwest::blocking::ClientBuilder, OtexError> {
+        let invalid = |err: reqwest::Error| OtexError::InvalidConfig(format!("invalid TLS configuration: {}", err));
+
+        if let Some(path) = &otlp.ca_certificate {
+            let certificate = reqwest::Certificate::from_pem(&super::read_pem(path)?).map_err(invalid)?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let (Some(certificate), Some(key)) = (&otlp.client_certificate, &otlp.client_key) {
+            let mut pem = super::read_pem(certificate)?;
+            pem.push(b'\n');
+            pem.extend(super::read_pem(key)?);
+            builder = builder.identity(reqwest::Identity::from_pem(&pem).map_err(invalid)?);
+        }
+        Ok(builder)
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn with_tls(
+        builder: reqwest::blocking::ClientBuilder,
+        otlp: &OtlpConfig,
+    ) -> Result<reqwest::blocking::ClientBuilder, OtexError> {
+        if super::has_tls(otlp) {
+            return Err(super::feature_required("TLS configuration", "tls"));
+        }
+        Ok(builder)
+    }
+
+    #[async_trait::async_trait]
+    impl opentelemetry_http::HttpClient for HttpClient {
+        async fn send_bytes(&self, request: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+            #[cfg(feature = "gzip")]
+            let request = if self.gzip { gzip(request)? } else { request };
+
+            self.client.send_bytes(request).await
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(request: Request<Bytes>) -> Result<Request<Bytes>, HttpError> {
+        use std::io::Write;
+
+        let (mut parts, body) = request.into_parts();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&body)?;
+        let body = encoder.finish()?;
+
+        parts.headers.insert(
+            http::header::CONTENT_ENCODING,
+            http::HeaderValue::from_static("gzip"),
+        );
+        parts.headers.remove(http::header::CONTENT_LENGTH);
+        Ok(Request::from_parts(parts, Bytes::from(body)))
+    }
+
+    #[cfg(all(test, feature = "gzip"))]
+    mod tests {
+        use std::io::Read;
+
+        use opentelemetry_http::{Bytes, Request};
+
+        #[test]
+        fn gzip_request() {
+            let request = Request::builder()
+                .uri("http://collector:4318/v1/traces")
+                .body(Bytes::from_static(b"payload"))
+                .unwrap();
+
+            let request = super::gzip(request).unwrap();
+
+            assert_eq!(request.headers()[http::header::CONTENT_ENCODING], "gzip");
+            let mut body = String::new();
+            flate2::read::GzDecoder::new(&request.body()[..])
+                .read_to_string(&mut body)
+                .unwrap();
+            assert_eq!(body, "payload");
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "grpc")))]
+mod tests {
+    use crate::config::{Config, Protocol};
+
+    #[test]
+    fn missing_feature() {
+        let config = Config::builder().with_protocol(Protocol::Grpc).build();
+
+        let error = super::span_exporter(&config).unwrap_err();
+        assert!(error.to_string().contains("`grpc` feature"), "{}", error);
+    }
+}
@@ -208,7 +208,8 @@ mod tests {
         );
 
         let context = extract_context_from_headers(&headers);
-        let span_context = context.span().span_context();
+        let span = context.span();
+        let span_context = span.span_context();
 
         assert!(span_context.is_valid());
         assert!(span_context.is_remote());
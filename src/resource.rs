@@ -0,0 +1,243 @@
+//! Resource detection.
+//!
+//! otex builds a single [`Resource`] in [`crate::try_init`] and attaches it to
+//! the tracer, logger and meter providers. It is assembled from the built-in
+//! detectors below and the service attributes of the [`Config`], with the
+//! config taking precedence.
+
+use std::path::Path;
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::resource::{ResourceDetector, TelemetryResourceDetector};
+
+use crate::config::Config;
+
+pub(crate) fn build_resource(config: &Config) -> Resource {
+    let mut builder = Resource::builder_empty().with_detector(Box::new(TelemetryResourceDetector));
+
+    if config.resource_detectors {
+        builder = builder.with_detectors(&[
+            Box::new(HostResourceDetector),
+            Box::new(OsResourceDetector),
+            Box::new(ProcessResourceDetector),
+            Box::new(ContainerResourceDetector),
+            Box::new(KubernetesResourceDetector),
+        ]);
+    }
+
+    builder = builder.with_attributes(config.resource_attributes.iter().cloned());
+
+    let mut attributes = vec![KeyValue::new("service.name", config.service_name.clone())];
+    if let Some(version) = &config.service_version {
+        attributes.push(KeyValue::new("service.version", version.clone()));
+    }
+    if let Some(namespace) = &config.service_namespace {
+        attributes.push(KeyValue::new("service.namespace", namespace.clone()));
+    }
+
+    builder.with_attributes(attributes).build()
+}
+
+/// Detects `host.name` and `host.arch`.
+#[derive(Debug)]
+pub struct HostResourceDetector;
+
+impl ResourceDetector for HostResourceDetector {
+    fn detect(&self) -> Resource {
+        let mut attributes = vec![KeyValue::new("host.arch", std::env::consts::ARCH)];
+
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty());
+        if let Some(hostname) = hostname {
+            attributes.push(KeyValue::new("host.name", hostname));
+        }
+
+        Resource::builder_empty().with_attributes(attributes).build()
+    }
+}
+
+/// Detects `os.type`.
+#[derive(Debug)]
+pub struct OsResourceDetector;
+
+impl ResourceDetector for OsResourceDetector {
+    fn detect(&self) -> Resource {
+        Resource::builder_empty()
+            .with_attribute(KeyValue::new("os.type", os_type(std::env::consts::OS)))
+            .build()
+    }
+}
+
+/// Maps Rust's `target_os` names to the `os.type` values of the semantic
+/// conventions where they differ.
+fn os_type(os: &str) -> &str {
+    match os {
+        "macos" => "darwin",
+        "dragonfly" => "dragonflybsd",
+        os => os,
+    }
+}
+
+/// Detects `process.pid`, the executable and the runtime.
+#[derive(Debug)]
+pub struct ProcessResourceDetector;
+
+impl ResourceDetector for ProcessResourceDetector {
+    fn detect(&self) -> Resource {
+        let mut attributes = vec![
+            KeyValue::new("process.pid", i64::from(std::process::id())),
+            KeyValue::new("process.runtime.name", "rust"),
+        ];
+
+        if let Ok(executable) = std::env::current_exe() {
+            if let Some(name) = executable.file_name() {
+                attributes.push(KeyValue::new(
+                    "process.executable.name",
+                    name.to_string_lossy().into_owned(),
+                ));
+            }
+            attributes.push(KeyValue::new(
+                "process.executable.path",
+                executable.to_string_lossy().into_owned(),
+            ));
+        }
+
+        Resource::builder_empty().with_attributes(attributes).build()
+    }
+}
+
+/// Detects `container.id` from `/proc/self/cgroup`.
+#[derive(Debug)]
+pub struct ContainerResourceDetector;
+
+impl ResourceDetector for ContainerResourceDetector {
+    fn detect(&self) -> Resource {
+        let container_id = std::fs::read_to_string("/proc/self/cgroup")
+            .ok()
+            .and_then(|cgroup| container_id_from_cgroup(&cgroup));
+
+        match container_id {
+            Some(id) => Resource::builder_empty()
+                .with_attribute(KeyValue::new("container.id", id))
+                .build(),
+            None => Resource::builder_empty().build(),
+        }
+    }
+}
+
+/// Extracts a 64 character container ID from the contents of `/proc/self/cgroup`.
+///
+/// Handles the cgroup v1 layout (`/docker/<id>`, `/kubepods/.../<id>`) as well
+/// as systemd scopes (`docker-<id>.scope`, `cri-containerd-<id>.scope`, ...).
+fn container_id_from_cgroup(cgroup: &str) -> Option<String> {
+    const PREFIXES: [&str; 4] = ["docker-", "cri-containerd-", "crio-", "libpod-"];
+
+    cgroup.lines().find_map(|line| {
+        let segment = line.rsplit('/').next()?.trim();
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+        let id = PREFIXES
+            .iter()
+            .find_map(|prefix| segment.strip_prefix(prefix))
+            .unwrap_or(segment);
+
+        let is_container_id = id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit());
+        is_container_id.then(|| id.to_string())
+    })
+}
+
+/// Detects Kubernetes attributes when running in a pod.
+///
+/// Reads the downward API variables `K8S_POD_NAME`, `K8S_POD_UID`,
+/// `K8S_NAMESPACE_NAME` and `K8S_NODE_NAME` (or their `POD_*`/`NODE_NAME`
+/// equivalents), falling back to `HOSTNAME` and the service account
+/// namespace file.
+#[derive(Debug)]
+pub struct KubernetesResourceDetector;
+
+impl ResourceDetector for KubernetesResourceDetector {
+    fn detect(&self) -> Resource {
+        if std::env::var_os("KUBERNETES_SERVICE_HOST").is_none() {
+            return Resource::builder_empty().build();
+        }
+
+        let namespace_file = Path::new("/var/run/secrets/kubernetes.io/serviceaccount/namespace");
+        let namespace = first_env(&["K8S_NAMESPACE_NAME", "POD_NAMESPACE"]).or_else(|| {
+            std::fs::read_to_string(namespace_file)
+                .ok()
+                .map(|namespace| namespace.trim().to_string())
+        });
+
+        let attributes = [
+            ("k8s.pod.name", first_env(&["K8S_POD_NAME", "POD_NAME", "HOSTNAME"])),
+            ("k8s.pod.uid", first_env(&["K8S_POD_UID", "POD_UID"])),
+            ("k8s.namespace.name", namespace),
+            ("k8s.node.name", first_env(&["K8S_NODE_NAME", "NODE_NAME"])),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(KeyValue::new(key, value.filter(|v| !v.is_empty())?)));
+
+        Resource::builder_empty().with_attributes(attributes).build()
+    }
+}
+
+fn first_env(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| std::env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{Key, KeyValue, Value};
+
+    use super::{build_resource, container_id_from_cgroup, os_type};
+    use crate::config::Config;
+
+    const ID: &str = "3c2d4c5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c";
+
+    #[test]
+    fn os_types() {
+        assert_eq!(os_type("macos"), "darwin");
+        assert_eq!(os_type("dragonfly"), "dragonflybsd");
+        assert_eq!(os_type("linux"), "linux");
+    }
+
+    #[test]
+    fn container_id() {
+        let v1 = format!("12:pids:/docker/{ID}\n11:memory:/docker/{ID}\n");
+        assert_eq!(container_id_from_cgroup(&v1).as_deref(), Some(ID));
+
+        let kubepods = format!("0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-{ID}.scope");
+        assert_eq!(container_id_from_cgroup(&kubepods).as_deref(), Some(ID));
+
+        assert_eq!(container_id_from_cgroup("0::/\n"), None);
+        assert_eq!(container_id_from_cgroup("0::/user.slice/session-2.scope"), None);
+    }
+
+    #[test]
+    fn config_attributes() {
+        let config = Config::builder()
+            .with_service_name("billing")
+            .with_service_version("1.2.3")
+            .with_resource_attribute(KeyValue::new("deployment.environment", "staging"))
+            .with_resource_attribute(KeyValue::new("service.name", "overridden"))
+            .build();
+
+        let resource = build_resource(&config);
+
+        assert_eq!(resource.get(&Key::new("service.name")), Some(Value::from("billing")));
+        assert_eq!(resource.get(&Key::new("service.version")), Some(Value::from("1.2.3")));
+        assert_eq!(
+            resource.get(&Key::new("deployment.environment")),
+            Some(Value::from("staging"))
+        );
+        assert!(resource.get(&Key::new("process.pid")).is_some());
+        assert!(resource.get(&Key::new("telemetry.sdk.name")).is_some());
+
+        let config = Config::builder().with_resource_detectors(false).build();
+        assert!(build_resource(&config).get(&Key::new("process.pid")).is_none());
+    }
+}
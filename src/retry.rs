@@ -0,0 +1,583 @@
+//! A persistent retry queue for OTLP exports.
+//!
+//! The exporters here wrap an OTLP exporter. When an export fails, the batch
+//! is written as an OTLP-JSON request to `<dir>/<signal>/` and a background
+//! thread per signal posts the spooled requests to the collector, oldest
+//! first, backing off exponentially while it stays unreachable. Batches left
+//! over from a previous run are sent too.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use opentelemetry_http::Bytes;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::logs::LogBatch;
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::trace::SpanData;
+
+use crate::config::{Config, Protocol, RetryQueue, SignalConfig};
+use crate::error::OtexError;
+use crate::otlp::http_client::HttpClient;
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:4318";
+
+/// The spooled batches of one signal.
+#[derive(Debug)]
+pub(crate) struct Spool {
+    signal: &'static str,
+    dir: PathBuf,
+    url: String,
+    headers: Vec<(String, String)>,
+    queue: RetryQueue,
+    client: HttpClient,
+    state: Mutex<State>,
+    wake: Condvar,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Total size of the spooled files.
+    size: u64,
+    sequence: u64,
+    /// Set when a live export succeeded, to replay without waiting for the backoff.
+    ready: bool,
+    stopped: bool,
+    /// Batches the collector accepted but that could not be deleted, so they
+    /// are not sent again.
+    sent: HashSet<PathBuf>,
+}
+
+impl Spool {
+    /// Opens the queue of `signal` and starts its replay thread, or returns
+    /// `None` when no retry queue is configured.
+    pub(crate) fn open(
+        config: &Config,
+        signal: &'static str,
+        signal_config: &SignalConfig,
+        path: &str,
+    ) -> Result<Option<Arc<Self>>, OtexError> {
+        let Some(queue) = &config.retry_queue else {
+            return Ok(None);
+        };
+        if signal_config.protocol == Protocol::Grpc {
+            return Err(OtexError::InvalidConfig(
+                "the retry queue requires an HTTP protocol".to_string(),
+            ));
+        }
+
+        let dir = queue.dir.join(signal);
+        let size = std::fs::create_dir_all(&dir)
+            .and_then(|_| spooled(&dir))
+            .map_err(|source| OtexError::File { path: dir.clone(), source })?
+            .iter()
+            .map(|(_, size)| size)
+            .sum();
+        let url = config
+            .signal_endpoint(signal_config, path)
+            .unwrap_or_else(|| format!("{}{}", DEFAULT_ENDPOINT, path));
+
+        let spool = Arc::new(Self {
+            signal,
+            dir,
+            url,
+            headers: config.otlp.headers.clone(),
+            queue: queue.clone(),
+            client: HttpClient::new(&config.otlp)?,
+            state: Mutex::new(State { size, ..State::default() }),
+            wake: Condvar::new(),
+            worker: Mutex::new(None),
+        });
+
+        let worker = Arc::clone(&spool);
+        let handle = std::thread::Builder::new()
+            .name(format!("otex-retry-{}", signal))
+            .spawn(move || worker.run())
+            .inspect_err(|e| log::error!("failed to spawn retry queue thread: {}", e))
+            .ok();
+        *spool.worker.lock().unwrap() = handle;
+        Ok(Some(spool))
+    }
+
+    /// Queues a batch whose export failed with `error`. The export counts as
+    /// successful once the batch is on disk.
+    fn retry_later(&self, request: &impl serde::Serialize, error: OTelSdkError) -> OTelSdkResult {
+        let written = serde_json::to_vec(request)
+            .map_err(|e| e.to_string())
+            .and_then(|body| self.write(&body).map_err(|e| e.to_string()));
+        match written {
+            Ok(()) => {
+                log::warn!("failed to export {}, queued for retry: {}", self.signal, error);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("failed to queue {} for retry: {}", self.signal, e);
+                Err(error)
+            }
+        }
+    }
+
+    fn write(&self, body: &[u8]) -> std::io::Result<()> {
+        let len = body.len() as u64;
+        if len > self.queue.max_bytes {
+            log::warn!("dropping {} batch of {} bytes, larger than the retry queue", self.signal, len);
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.size + len > self.queue.max_bytes {
+            for (path, size) in spooled(&self.dir)? {
+                if state.size + len <= self.queue.max_bytes {
+                    break;
+                }
+                if state.sent.contains(&path) {
+                    continue;
+                }
+                if std::fs::remove_file(&path).is_ok() {
+                    log::warn!("retry queue full, dropped queued {} batch {}", self.signal, path.display());
+                    state.size = state.size.saturating_sub(size);
+                }
+            }
+        }
+
+        // File names sort in the order the batches were queued.
+        state.sequence += 1;
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let name = format!("{:020}-{:06}", nanos, state.sequence % 1_000_000);
+        let partial = self.dir.join(format!("{}.tmp", name));
+        std::fs::write(&partial, body)?;
+        std::fs::rename(&partial, self.dir.join(format!("{}.json", name)))?;
+
+        state.size += len;
+        self.wake.notify_all();
+        Ok(())
+    }
+
+    /// Tells the replay thread that the collector is reachable again.
+    fn ready(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.size > 0 && !state.ready {
+            state.ready = true;
+            self.wake.notify_all();
+        }
+    }
+
+    fn run(&self) {
+        let mut backoff = self.queue.initial_backoff;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            // Wait for a queued batch, then for the backoff unless a live
+            // export just succeeded.
+            while state.size == 0 && !state.stopped {
+                state = self.wake.wait(state).unwrap();
+            }
+            let deadline = Instant::now() + backoff;
+            while !state.ready && !state.stopped {
+                let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                    break;
+                };
+                state = self.wake.wait_timeout(state, timeout).unwrap().0;
+            }
+            if state.stopped {
+                return;
+            }
+            state.ready = false;
+            drop(state);
+
+            backoff = match self.replay() {
+                Ok(()) => self.queue.initial_backoff,
+                Err(e) => {
+                    let backoff = (backoff * 2).min(self.queue.max_backoff);
+                    log::warn!("failed to send queued {}, retrying in {:?}: {}", self.signal, backoff, e);
+                    backoff
+                }
+            };
+            state = self.state.lock().unwrap();
+        }
+    }
+
+    /// Sends the queued batches until one fails.
+    fn replay(&self) -> Result<(), String> {
+        for (path, size) in spooled(&self.dir).map_err(|e| e.to_string())? {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.stopped {
+                    return Ok(());
+                }
+                if state.sent.contains(&path) {
+                    if std::fs::remove_file(&path).is_ok() {
+                        state.sent.remove(&path);
+                    }
+                    continue;
+                }
+            }
+            // Missing when dropped from a full queue in the meantime.
+            let Ok(body) = std::fs::read(&path) else {
+                continue;
+            };
+
+            let status = self.send(body).map_err(|e| e.to_string())?;
+            if status.is_client_error() && status != http::StatusCode::TOO_MANY_REQUESTS {
+                log::warn!("dropping queued {} batch rejected with {}", self.signal, status);
+            } else if !status.is_success() {
+                return Err(format!("collector responded with {}", status));
+            }
+
+            let mut state = self.state.lock().unwrap();
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::warn!("failed to remove sent {} batch {}: {}", self.signal, path.display(), e);
+                state.sent.insert(path);
+            }
+            state.size = state.size.saturating_sub(size);
+        }
+        Ok(())
+    }
+
+    fn send(&self, body: Vec<u8>) -> Result<http::StatusCode, opentelemetry_http::HttpError> {
+        let mut request = http::Request::post(&self.url)
+            .header(http::header::CONTENT_TYPE, "application/json");
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        self.client.send(request.body(Bytes::from(body))?)
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.wake.notify_all();
+        if let Some(handle) = self.worker.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Lists the queued batches in `dir`, oldest first, with their sizes.
+fn spooled(dir: &Path) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            files.push((path, entry.metadata()?.len()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[derive(Debug)]
+pub(crate) struct RetrySpanExporter<E> {
+    inner: E,
+    spool: Option<Arc<Spool>>,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl<E> RetrySpanExporter<E> {
+    pub(crate) fn new(inner: E, spool: Option<Arc<Spool>>) -> Self {
+        Self {
+            inner,
+            spool,
+            resource: ResourceAttributesWithSchema::default(),
+        }
+    }
+}
+
+impl<E: opentelemetry_sdk::trace::SpanExporter> opentelemetry_sdk::trace::SpanExporter for RetrySpanExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        use opentelemetry_proto::transform::trace::tonic::group_spans_by_resource_and_scope;
+
+        let Some(spool) = &self.spool else {
+            return self.inner.export(batch).await;
+        };
+        match self.inner.export(batch.clone()).await {
+            Ok(()) => {
+                spool.ready();
+                Ok(())
+            }
+            Err(error) => {
+                let request = ExportTraceServiceRequest {
+                    resource_spans: group_spans_by_resource_and_scope(batch, &self.resource),
+                };
+                spool.retry_later(&request, error)
+            }
+        }
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        let result = self.inner.shutdown_with_timeout(timeout);
+        if let Some(spool) = &self.spool {
+            spool.stop();
+        }
+        result
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+        self.inner.set_resource(resource);
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RetryLogExporter<E> {
+    inner: E,
+    spool: Option<Arc<Spool>>,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl<E> RetryLogExporter<E> {
+    pub(crate) fn new(inner: E, spool: Option<Arc<Spool>>) -> Self {
+        Self {
+            inner,
+            spool,
+            resource: ResourceAttributesWithSchema::default(),
+        }
+    }
+}
+
+impl<E: opentelemetry_sdk::logs::LogExporter> opentelemetry_sdk::logs::LogExporter for RetryLogExporter<E> {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        use opentelemetry_proto::transform::logs::tonic::group_logs_by_resource_and_scope;
+
+        let Some(spool) = &self.spool else {
+            return self.inner.export(batch).await;
+        };
+        // The batch is consumed by the export, keep the records for the queue.
+        let records: Vec<_> = batch.iter().collect();
+        match self.inner.export(LogBatch::new(&records)).await {
+            Ok(()) => {
+                spool.ready();
+                Ok(())
+            }
+            Err(error) => {
+                let request = ExportLogsServiceRequest {
+                    resource_logs: group_logs_by_resource_and_scope(LogBatch::new(&records), &self.resource),
+                };
+                spool.retry_later(&request, error)
+            }
+        }
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        let result = self.inner.shutdown_with_timeout(timeout);
+        if let Some(spool) = &self.spool {
+            spool.stop();
+        }
+        result
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+        self.inner.set_resource(resource);
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RetryMetricExporter<E> {
+    inner: E,
+    spool: Option<Arc<Spool>>,
+}
+
+impl<E> RetryMetricExporter<E> {
+    pub(crate) fn new(inner: E, spool: Option<Arc<Spool>>) -> Self {
+        Self { inner, spool }
+    }
+}
+
+impl<E: opentelemetry_sdk::metrics::exporter::PushMetricExporter>
+    opentelemetry_sdk::metrics::exporter::PushMetricExporter for RetryMetricExporter<E>
+{
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        let Some(spool) = &self.spool else {
+            return self.inner.export(metrics).await;
+        };
+        match self.inner.export(metrics).await {
+            Ok(()) => {
+                spool.ready();
+                Ok(())
+            }
+            Err(error) => spool.retry_later(&ExportMetricsServiceRequest::from(metrics), error),
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        let result = self.inner.shutdown_with_timeout(timeout);
+        if let Some(spool) = &self.spool {
+            spool.stop();
+        }
+        result
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.inner.temporality()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+    use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+
+    use super::{RetrySpanExporter, Spool, spooled};
+    use crate::config::{Config, RetryQueue};
+
+    /// Fails every export until `up` is set.
+    #[derive(Debug, Clone, Default)]
+    struct Collector {
+        up: Arc<AtomicBool>,
+    }
+
+    impl SpanExporter for Collector {
+        async fn export(&self, _batch: Vec<SpanData>) -> OTelSdkResult {
+            if self.up.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(OTelSdkError::InternalFailure("connection refused".to_string()))
+            }
+        }
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("otex-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn queues_and_replays_failed_batches() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"resourceSpans\"") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let dir = temp_dir("retry");
+        let queue = RetryQueue::new(&dir).with_initial_backoff(Duration::from_millis(10));
+        let config = Config::builder()
+            .with_endpoint(endpoint)
+            .with_header("x-api-key", "secret")
+            .with_retry_queue(queue)
+            .build();
+        let spool = Spool::open(&config, "traces", &config.traces, "/v1/traces").unwrap();
+
+        let collector = Collector::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(RetrySpanExporter::new(collector.clone(), spool))
+            .build();
+        provider.tracer("test").in_span("lost", |_| {});
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces "), "{}", request);
+        assert!(request.contains("x-api-key: secret"), "{}", request);
+        assert!(request.contains("\"name\":\"lost\""), "{}", request);
+
+        // Deleted once the collector accepted the batch.
+        let traces = dir.join("traces");
+        for _ in 0..100 {
+            if spooled(&traces).unwrap().is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(spooled(&traces).unwrap().is_empty());
+
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn drops_oldest_batches_when_full() {
+        let dir = temp_dir("retry-full");
+        // Nothing listens on the endpoint, so batches stay queued.
+        let queue = RetryQueue::new(&dir)
+            .with_max_bytes(25)
+            .with_initial_backoff(Duration::from_secs(60));
+        let config = Config::builder()
+            .with_endpoint("http://127.0.0.1:9")
+            .with_retry_queue(queue)
+            .build();
+        let spool = Spool::open(&config, "logs", &config.logs, "/v1/logs").unwrap().unwrap();
+
+        for body in ["{\"batch\":1}", "{\"batch\":2}", "{\"batch\":3}", "{\"too\":\"large for the queue\"}"] {
+            spool.write(body.as_bytes()).unwrap();
+        }
+
+        let queued: Vec<String> = spooled(&dir.join("logs"))
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| std::fs::read_to_string(path).unwrap())
+            .collect();
+        assert_eq!(queued, ["{\"batch\":2}", "{\"batch\":3}"]);
+
+        spool.stop();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_sent_batches() {
+        let dir = temp_dir("retry-sent");
+        // Nothing listens on the endpoint, so any resend would fail.
+        let queue = RetryQueue::new(&dir).with_initial_backoff(Duration::from_secs(60));
+        let config = Config::builder()
+            .with_endpoint("http://127.0.0.1:9")
+            .with_retry_queue(queue)
+            .build();
+        let spool = Spool::open(&config, "logs", &config.logs, "/v1/logs").unwrap().unwrap();
+
+        spool.write(b"{\"batch\":1}").unwrap();
+        let (path, _) = spooled(&dir.join("logs")).unwrap().remove(0);
+        spool.state.lock().unwrap().sent.insert(path);
+
+        spool.replay().unwrap();
+        assert!(spooled(&dir.join("logs")).unwrap().is_empty());
+        assert!(spool.state.lock().unwrap().sent.is_empty());
+
+        spool.stop();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn requires_http() {
+        let config = Config::builder()
+            .with_protocol(crate::Protocol::Grpc)
+            .with_retry_queue(RetryQueue::new(temp_dir("retry-grpc")))
+            .build();
+
+        let error = Spool::open(&config, "traces", &config.traces, "/v1/traces").unwrap_err();
+        assert!(error.to_string().contains("HTTP protocol"), "{}", error);
+    }
+}
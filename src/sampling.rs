@@ -0,0 +1,397 @@
+//! Head and tail sampling.
+//!
+//! The [`Sampler`] configured on [`crate::Config`] decides for every new span,
+//! unless one of the span name overrides matches. Overrides let cheap, noisy
+//! spans such as health checks be dropped while business spans stay sampled.
+//!
+//! [`TailSamplingProcessor`] then optionally holds back the sampled spans
+//! until their whole trace can be judged, see [`TailSampling`].
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use opentelemetry::trace::{Link, SamplingResult, SpanKind, Status, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{ShouldSample, Span, SpanData, SpanProcessor};
+
+use crate::config::{AttributeRule, Config, Sampler, TailSampling};
+
+pub(crate) fn build_sampler(config: &Config) -> SpanNameSampler {
+    SpanNameSampler {
+        overrides: config
+            .span_samplers
+            .iter()
+            .map(|(pattern, sampler)| (pattern.clone(), sdk_sampler(sampler)))
+            .collect(),
+        default: sdk_sampler(&config.sampler),
+    }
+}
+
+fn sdk_sampler(sampler: &Sampler) -> opentelemetry_sdk::trace::Sampler {
+    use opentelemetry_sdk::trace::Sampler as Sdk;
+
+    match sampler {
+        Sampler::AlwaysOn => Sdk::AlwaysOn,
+        Sampler::AlwaysOff => Sdk::AlwaysOff,
+        Sampler::TraceIdRatio(ratio) => Sdk::TraceIdRatioBased(*ratio),
+        Sampler::ParentBased(root) => Sdk::ParentBased(Box::new(sdk_sampler(root))),
+    }
+}
+
+/// Picks the sampler by span name, falling back to the default sampler.
+///
+/// A pattern matches a span name exactly, or as a prefix when it ends with
+/// `*`. Later overrides take precedence over earlier ones.
+#[derive(Debug, Clone)]
+pub(crate) struct SpanNameSampler {
+    overrides: Vec<(String, opentelemetry_sdk::trace::Sampler)>,
+    default: opentelemetry_sdk::trace::Sampler,
+}
+
+impl SpanNameSampler {
+    fn sampler_for(&self, name: &str) -> &opentelemetry_sdk::trace::Sampler {
+        self.overrides
+            .iter()
+            .rev()
+            .find(|(pattern, _)| matches(pattern, name))
+            .map_or(&self.default, |(_, sampler)| sampler)
+    }
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+impl ShouldSample for SpanNameSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.sampler_for(name)
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+impl TailSampling {
+    fn keep(&self, spans: &[SpanData]) -> bool {
+        if self.keep_errors && spans.iter().any(|span| matches!(span.status, Status::Error { .. })) {
+            return true;
+        }
+
+        if let Some(threshold) = self.latency_threshold {
+            let start = spans.iter().map(|span| span.start_time).min();
+            let end = spans.iter().map(|span| span.end_time).max();
+            if let (Some(start), Some(end)) = (start, end)
+                && end.duration_since(start).unwrap_or_default() >= threshold
+            {
+                return true;
+            }
+        }
+
+        spans.iter().any(|span| {
+            self.attribute_rules.iter().any(|rule| match rule {
+                AttributeRule::Present(key) => span.attributes.iter().any(|kv| kv.key == *key),
+                AttributeRule::Equals(attribute) => span.attributes.contains(attribute),
+            })
+        })
+    }
+}
+
+/// Buffers finished spans per trace and forwards the traces kept by the
+/// [`TailSampling`] policy to `inner`, typically the batch processor.
+///
+/// Buffered traces are decided once their decision wait elapses, checked by a
+/// background thread, and all at once on flush and shutdown. Spans ending
+/// after their trace was decided follow that decision for another decision
+/// wait and are then buffered as a new trace.
+#[derive(Debug)]
+pub(crate) struct TailSamplingProcessor<P> {
+    state: Arc<State<P>>,
+    ticker: OnceLock<Ticker>,
+}
+
+#[derive(Debug)]
+struct State<P> {
+    policy: TailSampling,
+    traces: Mutex<Traces>,
+    inner: P,
+}
+
+#[derive(Debug, Default)]
+struct Traces {
+    pending: HashMap<TraceId, Vec<SpanData>>,
+    /// Pending traces in the order their first span ended.
+    order: VecDeque<(Instant, TraceId)>,
+    decided: HashMap<TraceId, (bool, Instant)>,
+}
+
+#[derive(Debug)]
+struct Ticker {
+    stop: Mutex<Option<mpsc::Sender<()>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<P: SpanProcessor + 'static> TailSamplingProcessor<P> {
+    pub(crate) fn new(policy: TailSampling, inner: P) -> Self {
+        Self {
+            state: Arc::new(State {
+                policy,
+                traces: Mutex::new(Traces::default()),
+                inner,
+            }),
+            ticker: OnceLock::new(),
+        }
+    }
+
+    /// Starts the thread deciding expired traces. Deferred to the first span
+    /// so that `set_resource` can still reach the inner processor.
+    fn start_ticker(&self) -> Ticker {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let state = Arc::clone(&self.state);
+        let tick = (self.state.policy.decision_wait / 4).max(Duration::from_millis(10));
+
+        let handle = std::thread::Builder::new()
+            .name("otex-tail-sampling".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(tick) {
+                    state.decide(false);
+                }
+            })
+            .inspect_err(|e| log::error!("failed to spawn tail sampling thread: {}", e))
+            .ok();
+
+        Ticker {
+            stop: Mutex::new(Some(stop)),
+            handle: Mutex::new(handle),
+        }
+    }
+
+    fn stop_ticker(&self) {
+        let Some(ticker) = self.ticker.get() else {
+            return;
+        };
+        ticker.stop.lock().unwrap().take();
+        if let Some(handle) = ticker.handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<P: SpanProcessor> State<P> {
+    /// Decides the traces whose wait has elapsed, or every pending trace when
+    /// `all` is set, and forwards the kept spans.
+    fn decide(&self, all: bool) {
+        let now = Instant::now();
+        let wait = self.policy.decision_wait;
+        let mut kept = Vec::new();
+        {
+            let mut traces = self.traces.lock().unwrap();
+            while let Some(&(first_end, trace_id)) = traces.order.front() {
+                let due = now.duration_since(first_end) >= wait;
+                let over_capacity = traces.pending.len() > self.policy.max_traces;
+                if !(all || due || over_capacity) {
+                    break;
+                }
+                traces.order.pop_front();
+                if let Some(spans) = traces.pending.remove(&trace_id) {
+                    let keep = self.policy.keep(&spans);
+                    traces.decided.insert(trace_id, (keep, now));
+                    if keep {
+                        kept.extend(spans);
+                    }
+                }
+            }
+            traces.decided.retain(|_, (_, decided_at)| now.duration_since(*decided_at) < wait);
+        }
+
+        for span in kept {
+            self.inner.on_end(span);
+        }
+    }
+}
+
+impl<P: SpanProcessor + 'static> SpanProcessor for TailSamplingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.state.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        self.ticker.get_or_init(|| self.start_ticker());
+
+        let trace_id = span.span_context.trace_id();
+        let mut traces = self.state.traces.lock().unwrap();
+        if let Some(&(keep, _)) = traces.decided.get(&trace_id) {
+            drop(traces);
+            if keep {
+                self.state.inner.on_end(span);
+            }
+            return;
+        }
+
+        match traces.pending.get_mut(&trace_id) {
+            Some(spans) => spans.push(span),
+            None => {
+                traces.pending.insert(trace_id, vec![span]);
+                traces.order.push_back((Instant::now(), trace_id));
+            }
+        }
+        let over_capacity = traces.pending.len() > self.state.policy.max_traces;
+        drop(traces);
+
+        if over_capacity {
+            self.state.decide(false);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.state.decide(true);
+        self.state.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.stop_ticker();
+        self.state.decide(true);
+        self.state.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        match Arc::get_mut(&mut self.state) {
+            Some(state) => state.inner.set_resource(resource),
+            None => log::warn!("tail sampling processor already running, resource not set"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use opentelemetry::trace::{
+        SamplingDecision, Span, SpanKind, Status, TraceContextExt, TraceId, Tracer, TracerProvider,
+    };
+    use opentelemetry::{Context, KeyValue};
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{SdkTracerProvider, ShouldSample, SpanData, SpanProcessor};
+
+    use super::{TailSamplingProcessor, build_sampler};
+    use crate::config::{Config, Sampler, TailSampling};
+
+    fn decision(config: &Config, name: &str) -> SamplingDecision {
+        build_sampler(config)
+            .should_sample(None, TraceId::from(1), name, &SpanKind::Server, &[], &[])
+            .decision
+    }
+
+    #[test]
+    fn span_name_overrides() {
+        let config = Config::builder()
+            .with_sampler(Sampler::AlwaysOn)
+            .with_span_sampler("GET /health*", Sampler::AlwaysOff)
+            .with_span_sampler("GET /healthz/deep", Sampler::AlwaysOn)
+            .with_span_sampler("ping", Sampler::AlwaysOff)
+            .build();
+
+        assert_eq!(decision(&config, "checkout"), SamplingDecision::RecordAndSample);
+        assert_eq!(decision(&config, "ping"), SamplingDecision::Drop);
+        assert_eq!(decision(&config, "pings"), SamplingDecision::RecordAndSample);
+        assert_eq!(decision(&config, "GET /healthz"), SamplingDecision::Drop);
+        assert_eq!(decision(&config, "GET /healthz/deep"), SamplingDecision::RecordAndSample);
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanProcessor for Collect {
+        fn on_start(&self, _span: &mut opentelemetry_sdk::trace::Span, _cx: &Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            self.0.lock().unwrap().push(span);
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+    }
+
+    fn exported_traces(policy: TailSampling, record: impl FnOnce(&opentelemetry_sdk::trace::SdkTracer)) -> Vec<String> {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(TailSamplingProcessor::new(policy, collect.clone()))
+            .build();
+
+        record(&provider.tracer("test"));
+        provider.force_flush().unwrap();
+
+        let mut names: Vec<_> = collect.0.lock().unwrap().iter().map(|span| span.name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    fn trace(tracer: &opentelemetry_sdk::trace::SdkTracer, root: &'static str, child: impl FnOnce(&mut opentelemetry_sdk::trace::Span)) {
+        let root_span = tracer.start(root);
+        let cx = Context::current_with_span(root_span);
+        let mut child_span = tracer.start_with_context(format!("{}.child", root), &cx);
+        child(&mut child_span);
+        child_span.end();
+        cx.span().end();
+    }
+
+    #[test]
+    fn tail_sampling_keeps_whole_traces() {
+        let policy = TailSampling::new()
+            .with_decision_wait(Duration::from_secs(3600))
+            .with_attribute(KeyValue::new("customer.tier", "gold"));
+
+        let names = exported_traces(policy, |tracer| {
+            trace(tracer, "ok", |_| {});
+            trace(tracer, "failed", |span| span.set_status(Status::error("boom")));
+            trace(tracer, "gold", |span| span.set_attribute(KeyValue::new("customer.tier", "gold")));
+            trace(tracer, "silver", |span| span.set_attribute(KeyValue::new("customer.tier", "silver")));
+        });
+
+        assert_eq!(names, ["failed", "failed.child", "gold", "gold.child"]);
+    }
+
+    #[test]
+    fn tail_sampling_latency_and_capacity() {
+        let policy = TailSampling::new()
+            .with_decision_wait(Duration::from_secs(3600))
+            .with_latency_threshold(Duration::from_millis(20));
+
+        let names = exported_traces(policy, |tracer| {
+            trace(tracer, "fast", |_| {});
+            trace(tracer, "slow", |_| std::thread::sleep(Duration::from_millis(25)));
+        });
+        assert_eq!(names, ["slow", "slow.child"]);
+
+        // Over capacity, the oldest trace is decided as soon as the next one starts buffering.
+        let collect = Collect::default();
+        let policy = TailSampling::new()
+            .with_decision_wait(Duration::from_secs(3600))
+            .with_max_traces(1);
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(TailSamplingProcessor::new(policy, collect.clone()))
+            .build();
+        let tracer = provider.tracer("test");
+        trace(&tracer, "failed", |span| span.set_status(Status::error("boom")));
+        trace(&tracer, "ok", |_| {});
+        assert_eq!(collect.0.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,473 @@
+//! Capturing telemetry in tests.
+//!
+//! [`init`] initializes otex with in-memory exporters instead of the
+//! configured pipeline, so that tests can assert on the spans, log records
+//! and metric points their code produced:
+//!
+//! ```ignore
+//! let telemetry = otex::testing::init();
+//! {
+//!     let _span = otex::context!("checkout", otex::trace::SpanKind::Internal, items = 3);
+//! }
+//! let span = telemetry.span("checkout").unwrap();
+//! assert!(span.attributes.contains(&otex::KeyValue::new("items", 3)));
+//! ```
+//!
+//! Spans and log records are captured as soon as they end or are emitted.
+//! Requires the `testing` feature.
+//!
+//! [`init`] shares one set of providers across the whole test binary. To keep
+//! tests running in parallel from seeing each other's telemetry, use
+//! [`scoped`] instead, which records the current thread into fresh providers
+//! until the returned guard is dropped:
+//!
+//! ```ignore
+//! #[test]
+//! fn checkout() {
+//!     let telemetry = otex::testing::scoped();
+//!     run_checkout();
+//!     assert_eq!(telemetry.spans().len(), 1);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::OnceLock;
+
+use opentelemetry::{InstrumentationScope, KeyValue};
+use opentelemetry::logs::{LoggerProvider, Severity};
+use opentelemetry::metrics::{Meter, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::logs::{InMemoryLogExporter, SdkLogRecord, SdkLogger, SdkLoggerProvider};
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, SdkMeterProvider};
+use opentelemetry_sdk::trace::{
+    InMemorySpanExporter, SdkTracer, SdkTracerProvider, SimpleSpanProcessor, SpanData,
+};
+
+use crate::clock::ClockLogProcessor;
+use crate::config::Config;
+use crate::filter::LogFilterHandle;
+
+mod assert;
+
+pub use assert::{LogAssertion, LogMatcher, MetricAssertion, SpanAssertion};
+
+static TELEMETRY: OnceLock<Telemetry> = OnceLock::new();
+
+thread_local! {
+    static SCOPE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+/// Providers installed for the current thread by [`scoped`].
+struct Scope {
+    config: Config,
+    log_filter: LogFilterHandle,
+    telemetry: Telemetry,
+}
+
+/// Initializes otex with in-memory exporters, once per process, and returns
+/// the captured telemetry.
+///
+/// # Panics
+/// Panics if otex was already initialized with [`crate::init`].
+pub fn init() -> Telemetry {
+    TELEMETRY
+        .get_or_init(|| {
+            let config = Config::builder()
+                .with_service_name("otex-test")
+                .with_resource_detectors(false)
+                .build();
+            let telemetry = Telemetry::new(&config);
+            crate::init::install(
+                config,
+                Some(telemetry.tracer_provider.clone()),
+                Some(telemetry.logger_provider.clone()),
+                Some(telemetry.meter_provider.clone()),
+            )
+            .expect("failed to initialize otex for testing");
+            telemetry
+        })
+        .clone()
+}
+
+/// Records the current thread's telemetry into fresh in-memory exporters
+/// until the returned guard is dropped, which shuts them down again.
+///
+/// Spans, log records and metrics created on other threads, such as the
+/// workers of a multi-threaded tokio runtime, are not captured.
+pub fn scoped() -> ScopedTelemetry {
+    scoped_with(
+        Config::builder()
+            .with_service_name("otex-test")
+            .with_resource_detectors(false)
+            .build(),
+    )
+}
+
+/// Like [`scoped`], with the service name, resource, sampler, ID generator,
+/// clock and enabled signals of `config`.
+pub fn scoped_with(config: Config) -> ScopedTelemetry {
+    let telemetry = Telemetry::new(&config);
+    let log_filter = LogFilterHandle::new(config.log_filter.clone());
+    let scope = Scope {
+        config,
+        log_filter: log_filter.clone(),
+        telemetry: telemetry.clone(),
+    };
+    let previous = SCOPE.with(|current| current.replace(Some(scope)));
+    ScopedTelemetry {
+        telemetry,
+        log_filter,
+        previous,
+        _thread: PhantomData,
+    }
+}
+
+/// Returns the telemetry of the current [`scoped`] guard, or the one shared
+/// through [`init`] outside of a scope.
+///
+/// # Panics
+/// Panics if neither [`scoped`] nor [`init`] was called.
+pub fn current() -> Telemetry {
+    SCOPE
+        .with(|scope| scope.borrow().as_ref().map(|scope| scope.telemetry.clone()))
+        .or_else(|| TELEMETRY.get().cloned())
+        .expect("no telemetry scope active; call testing::scoped() or testing::init() first")
+}
+
+pub(crate) fn is_scoped() -> bool {
+    SCOPE.with(|scope| scope.borrow().is_some())
+}
+
+/// Applies `f` to the config of the current scope, if any.
+pub(crate) fn scoped_config<R>(f: impl FnOnce(&Config) -> R) -> Option<R> {
+    SCOPE.with(|scope| scope.borrow().as_ref().map(|scope| f(&scope.config)))
+}
+
+pub(crate) fn scoped_log_filter() -> Option<LogFilterHandle> {
+    SCOPE.with(|scope| scope.borrow().as_ref().map(|scope| scope.log_filter.clone()))
+}
+
+pub(crate) fn scoped_tracer() -> Option<SdkTracer> {
+    SCOPE.with(|scope| {
+        let scope = scope.borrow();
+        let scope = scope.as_ref()?;
+        if !scope.config.traces.enabled {
+            return None;
+        }
+        Some(scope.telemetry.tracer_provider.tracer(scope.config.service_name.clone()))
+    })
+}
+
+pub(crate) fn scoped_logger() -> Option<SdkLogger> {
+    SCOPE.with(|scope| {
+        let scope = scope.borrow();
+        let scope = scope.as_ref()?;
+        if !scope.config.logs.enabled {
+            return None;
+        }
+        Some(scope.telemetry.logger_provider.logger(scope.config.service_name.clone()))
+    })
+}
+
+pub(crate) fn scoped_meter() -> Option<Meter> {
+    SCOPE.with(|scope| {
+        let scope = scope.borrow();
+        let scope = scope.as_ref()?;
+        if !scope.config.metrics.enabled {
+            return None;
+        }
+        let name = InstrumentationScope::builder(scope.config.service_name.clone()).build();
+        Some(scope.telemetry.meter_provider.meter_with_scope(name))
+    })
+}
+
+/// Captures the current thread's telemetry, see [`scoped`].
+#[must_use = "dropping the guard immediately ends the scope"]
+pub struct ScopedTelemetry {
+    telemetry: Telemetry,
+    log_filter: LogFilterHandle,
+    previous: Option<Scope>,
+    // The scope belongs to the thread that created it.
+    _thread: PhantomData<*const ()>,
+}
+
+impl ScopedTelemetry {
+    /// Returns a handle changing the log filter of this scope, like
+    /// [`crate::OtexGuard::log_filter`].
+    pub fn log_filter(&self) -> LogFilterHandle {
+        self.log_filter.clone()
+    }
+}
+
+impl Deref for ScopedTelemetry {
+    type Target = Telemetry;
+
+    fn deref(&self) -> &Telemetry {
+        &self.telemetry
+    }
+}
+
+impl Drop for ScopedTelemetry {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        // The thread local is gone if the guard outlives it in another thread local.
+        let _ = SCOPE.try_with(|scope| scope.replace(previous));
+        let _ = self.telemetry.tracer_provider.shutdown();
+        let _ = self.telemetry.logger_provider.shutdown();
+        let _ = self.telemetry.meter_provider.shutdown();
+    }
+}
+
+/// Telemetry captured by the in-memory exporters.
+#[derive(Debug, Clone)]
+pub struct Telemetry {
+    spans: InMemorySpanExporter,
+    logs: InMemoryLogExporter,
+    metrics: InMemoryMetricExporter,
+    tracer_provider: SdkTracerProvider,
+    logger_provider: SdkLoggerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+/// A single data point of a metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricPoint {
+    pub name: String,
+    pub unit: String,
+    pub kind: MetricKind,
+    pub attributes: Vec<KeyValue>,
+    /// The sum or last value, or the sum of all recorded values for histograms.
+    pub value: f64,
+    /// The number of recorded values, for histograms.
+    pub count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    UpDownCounter,
+    Gauge,
+    Histogram,
+}
+
+impl Telemetry {
+    fn new(config: &Config) -> Self {
+        let spans = InMemorySpanExporter::default();
+        let logs = InMemoryLogExporter::default();
+        let metrics = InMemoryMetricExporter::default();
+
+        let resource = crate::resource::build_resource(config);
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_resource(resource.clone())
+            .with_sampler(crate::sampling::build_sampler(config))
+            .with_id_generator(crate::id::Ids::new(config.id_generator));
+        let tracer_provider =
+            crate::tracer::with_processor(tracer_provider, config, SimpleSpanProcessor::new(spans.clone()))
+                .build();
+        let mut logger_provider = SdkLoggerProvider::builder().with_resource(resource.clone());
+        if let Some(clock) = &config.clock {
+            logger_provider = logger_provider.with_log_processor(ClockLogProcessor::new(clock.clone()));
+        }
+        let logger_provider = logger_provider.with_simple_exporter(logs.clone()).build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_resource(resource)
+            .with_periodic_exporter(metrics.clone())
+            .build();
+
+        Self {
+            spans,
+            logs,
+            metrics,
+            tracer_provider,
+            logger_provider,
+            meter_provider,
+        }
+    }
+
+    /// Returns the finished spans, in the order they ended.
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.spans.get_finished_spans().unwrap_or_default()
+    }
+
+    /// Returns the last finished span named `name`.
+    pub fn span(&self, name: &str) -> Option<SpanData> {
+        self.spans().into_iter().rev().find(|span| span.name == name)
+    }
+
+    /// Returns the parent of `span`, if it has finished.
+    pub fn parent(&self, span: &SpanData) -> Option<SpanData> {
+        self.spans()
+            .into_iter()
+            .find(|parent| parent.span_context.span_id() == span.parent_span_id)
+    }
+
+    /// Returns the emitted log records, in the order they were emitted.
+    pub fn logs(&self) -> Vec<SdkLogRecord> {
+        let logs = self.logs.get_emitted_logs().unwrap_or_default();
+        logs.into_iter().map(|log| log.record).collect()
+    }
+
+    /// Returns the emitted log records with the given severity.
+    pub fn logs_with_severity(&self, severity: Severity) -> Vec<SdkLogRecord> {
+        let logs = self.logs().into_iter();
+        logs.filter(|log| log.severity_number() == Some(severity)).collect()
+    }
+
+    /// Collects the metrics and returns their current data points.
+    pub fn metrics(&self) -> Vec<MetricPoint> {
+        let _ = self.meter_provider.force_flush();
+        // Metrics are cumulative, so the last collection holds everything.
+        let collections = self.metrics.get_finished_metrics().unwrap_or_default();
+        let Some(collection) = collections.last() else {
+            return Vec::new();
+        };
+
+        let mut points = Vec::new();
+        for scope in collection.scope_metrics() {
+            for metric in scope.metrics() {
+                let mut push = |kind, attributes: Vec<KeyValue>, value, count| {
+                    points.push(MetricPoint {
+                        name: metric.name().to_string(),
+                        unit: metric.unit().to_string(),
+                        kind,
+                        attributes,
+                        value,
+                        count,
+                    });
+                };
+                match metric.data() {
+                    AggregatedMetrics::F64(data) => metric_points(data, |v| v, &mut push),
+                    AggregatedMetrics::U64(data) => metric_points(data, |v| v as f64, &mut push),
+                    AggregatedMetrics::I64(data) => metric_points(data, |v| v as f64, &mut push),
+                }
+            }
+        }
+        points
+    }
+
+    /// Returns the data points of the metric named `name`.
+    pub fn metric(&self, name: &str) -> Vec<MetricPoint> {
+        self.metrics().into_iter().filter(|point| point.name == name).collect()
+    }
+
+    /// Forgets the spans and log records captured so far. Metrics are
+    /// cumulative and keep their values.
+    pub fn reset(&self) {
+        self.spans.reset();
+        self.logs.reset();
+    }
+}
+
+fn metric_points<T: Copy>(
+    data: &MetricData<T>,
+    to_f64: impl Fn(T) -> f64,
+    push: &mut impl FnMut(MetricKind, Vec<KeyValue>, f64, Option<u64>),
+) {
+    match data {
+        MetricData::Gauge(gauge) => {
+            for point in gauge.data_points() {
+                push(MetricKind::Gauge, point.attributes().cloned().collect(), to_f64(point.value()), None);
+            }
+        }
+        MetricData::Sum(sum) => {
+            let kind = if sum.is_monotonic() { MetricKind::Counter } else { MetricKind::UpDownCounter };
+            for point in sum.data_points() {
+                push(kind, point.attributes().cloned().collect(), to_f64(point.value()), None);
+            }
+        }
+        MetricData::Histogram(histogram) => {
+            for point in histogram.data_points() {
+                let attributes = point.attributes().cloned().collect();
+                push(MetricKind::Histogram, attributes, to_f64(point.sum()), Some(point.count()));
+            }
+        }
+        MetricData::ExponentialHistogram(histogram) => {
+            for point in histogram.data_points() {
+                let attributes = point.attributes().cloned().collect();
+                push(MetricKind::Histogram, attributes, to_f64(point.sum()), Some(point.count() as u64));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn scopes_are_isolated_per_thread() {
+        let threads: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|name| {
+                std::thread::spawn(move || {
+                    let telemetry = super::scoped();
+                    drop(crate::context!(name));
+                    telemetry.spans().into_iter().map(|span| span.name.into_owned()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let spans: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        assert_eq!(spans, [["first"], ["second"]]);
+    }
+
+    #[test]
+    fn current_without_scope() {
+        let panic = std::panic::catch_unwind(super::current).unwrap_err();
+
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("no telemetry scope active"), "{}", message);
+        // Nothing was installed globally.
+        assert!(crate::try_tracer().is_none());
+    }
+
+    #[test]
+    fn nested_scope_restores_outer() {
+        let outer = super::scoped();
+        {
+            let inner = super::scoped();
+            drop(crate::context!("inner"));
+            assert_eq!(inner.spans().len(), 1);
+        }
+        drop(crate::context!("outer"));
+
+        let names: Vec<_> = outer.spans().into_iter().map(|span| span.name).collect();
+        assert_eq!(names, ["outer"]);
+        assert_eq!(super::current().spans().len(), 1);
+    }
+
+    #[test]
+    fn deterministic_ids_and_clock() {
+        let clock = crate::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+        let config = crate::Config::builder()
+            .with_id_generator(crate::IdGenerator::Sequential)
+            .with_clock(clock.clone())
+            .build();
+        let telemetry = super::scoped_with(config);
+        {
+            let _parent = crate::context!("parent").attach();
+            clock.advance(Duration::from_millis(5));
+            let _child = crate::context!("child").attach();
+            crate::event!("checkpoint");
+            crate::info_log!("logged");
+            clock.advance(Duration::from_millis(10));
+        }
+
+        let child = telemetry.span("child").unwrap();
+        let parent = telemetry.span("parent").unwrap();
+        assert_eq!(parent.span_context.trace_id().to_string(), "00000000000000000000000000000001");
+        assert_eq!(parent.span_context.span_id().to_string(), "0000000000000001");
+        assert_eq!(child.span_context.span_id().to_string(), "0000000000000002");
+        assert_eq!(parent.start_time, UNIX_EPOCH + Duration::from_millis(1_000_000));
+        assert_eq!(child.start_time, UNIX_EPOCH + Duration::from_millis(1_000_005));
+        assert_eq!(child.end_time, UNIX_EPOCH + Duration::from_millis(1_000_015));
+        assert_eq!(child.events[0].timestamp, child.start_time);
+
+        let log = &telemetry.logs()[0];
+        assert_eq!(log.timestamp(), Some(child.start_time));
+        assert_eq!(log.observed_timestamp(), Some(child.start_time));
+    }
+}
@@ -0,0 +1,541 @@
+//! Fluent assertions on captured telemetry, used through [`crate::assert_span!`],
+//! [`crate::assert_log!`] and [`crate::assert_metric!`].
+//!
+//! A failed assertion panics with what was expected next to what was
+//! captured, e.g.
+//!
+//! ```text
+//! span "world" has no attribute child_attr=123
+//!   expected: child_attr=123
+//!   found:    child_attr=124, region="eu"
+//! ```
+
+use std::fmt::Write;
+
+use opentelemetry::logs::{AnyValue, Severity};
+use opentelemetry::trace::{SpanKind, Status};
+use opentelemetry::{Key, KeyValue, Value};
+use opentelemetry_sdk::logs::SdkLogRecord;
+use opentelemetry_sdk::trace::SpanData;
+
+use super::{MetricKind, MetricPoint, Telemetry};
+
+/// Asserts a span with the given name finished, see [`SpanAssertion`].
+///
+/// ```ignore
+/// otex::assert_span!("world").has_parent("hello").has_attr("child_attr", 123);
+/// ```
+#[macro_export]
+macro_rules! assert_span {
+    ($name:expr) => {
+        $crate::testing::SpanAssertion::new(&$crate::testing::current(), $name)
+    };
+}
+
+/// Asserts a log record matching every `field = value` was emitted, see
+/// [`LogMatcher`] for the fields. Severities can be given by name.
+///
+/// ```ignore
+/// otex::assert_log!(severity = Error, body = "error!").has_attr("test_key", "hello");
+/// ```
+#[macro_export]
+macro_rules! assert_log {
+    (@match $matcher:expr; severity = $severity:ident $(, $($rest:tt)*)?) => {
+        $crate::assert_log!(@match $matcher.severity($crate::logs::Severity::$severity); $($($rest)*)?)
+    };
+    (@match $matcher:expr; $field:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::assert_log!(@match $matcher.$field($value); $($($rest)*)?)
+    };
+    (@match $matcher:expr;) => {
+        $matcher.assert(&$crate::testing::current())
+    };
+    ($($fields:tt)*) => {
+        $crate::assert_log!(@match $crate::testing::LogMatcher::new(); $($fields)*)
+    };
+}
+
+/// Asserts a metric with the given name was recorded, see [`MetricAssertion`].
+///
+/// ```ignore
+/// otex::assert_metric!("requests").with_attr("route", "/").sum(2);
+/// ```
+#[macro_export]
+macro_rules! assert_metric {
+    ($name:expr) => {
+        $crate::testing::MetricAssertion::new(&$crate::testing::current(), $name)
+    };
+}
+
+#[track_caller]
+fn fail(title: impl std::fmt::Display, expected: impl std::fmt::Display, found: &[String]) -> ! {
+    let mut message = format!("{}\n  expected: {}\n  found:    ", title, expected);
+    if found.is_empty() {
+        message.push_str("nothing");
+    } else if found.len() == 1 || found.iter().all(|item| !item.contains(' ')) {
+        message.push_str(&found.join(", "));
+    } else {
+        for item in found {
+            let _ = write!(message, "\n    {}", item);
+        }
+    }
+    panic!("{}", message)
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s.as_str()),
+        other => other.to_string(),
+    }
+}
+
+fn format_any_value(value: &AnyValue) -> String {
+    match value {
+        AnyValue::String(s) => format!("{:?}", s.as_str()),
+        AnyValue::Int(i) => i.to_string(),
+        AnyValue::Double(d) => d.to_string(),
+        AnyValue::Boolean(b) => b.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn format_attributes<'a>(attributes: impl IntoIterator<Item = &'a KeyValue>) -> Vec<String> {
+    attributes
+        .into_iter()
+        .map(|kv| format!("{}={}", kv.key, format_value(&kv.value)))
+        .collect()
+}
+
+/// Assertions on a finished span, each panicking when it does not hold.
+#[derive(Debug)]
+pub struct SpanAssertion {
+    telemetry: Telemetry,
+    span: SpanData,
+}
+
+impl SpanAssertion {
+    /// Finds the last finished span named `name`.
+    #[track_caller]
+    pub fn new(telemetry: &Telemetry, name: &str) -> Self {
+        let spans = telemetry.spans();
+        match spans.iter().rev().find(|span| span.name == name) {
+            Some(span) => Self {
+                telemetry: telemetry.clone(),
+                span: span.clone(),
+            },
+            None => {
+                let names: Vec<String> = spans.iter().map(|span| format!("{:?}", span.name)).collect();
+                fail(format_args!("no span named {:?} finished", name), format!("{:?}", name), &names)
+            }
+        }
+    }
+
+    /// The span under test.
+    pub fn span(&self) -> &SpanData {
+        &self.span
+    }
+
+    #[track_caller]
+    pub fn has_parent(self, name: &str) -> Self {
+        match self.telemetry.parent(&self.span) {
+            Some(parent) if parent.name == name => self,
+            Some(parent) => fail(
+                format_args!("span {:?} has the wrong parent", self.span.name),
+                format!("{:?}", name),
+                &[format!("{:?}", parent.name)],
+            ),
+            None if self.span.parent_span_id == opentelemetry::trace::SpanId::INVALID => fail(
+                format_args!("span {:?} has no parent", self.span.name),
+                format!("{:?}", name),
+                &[],
+            ),
+            None => fail(
+                format_args!("the parent of span {:?} has not finished", self.span.name),
+                format!("{:?}", name),
+                &[format!("unfinished span {}", self.span.parent_span_id)],
+            ),
+        }
+    }
+
+    #[track_caller]
+    pub fn is_root(self) -> Self {
+        if self.span.parent_span_id != opentelemetry::trace::SpanId::INVALID {
+            let parent = match self.telemetry.parent(&self.span) {
+                Some(parent) => format!("{:?}", parent.name),
+                None => format!("unfinished span {}", self.span.parent_span_id),
+            };
+            fail(format_args!("span {:?} is not a root span", self.span.name), "no parent", &[parent]);
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn has_attr(self, key: impl Into<Key>, value: impl Into<Value>) -> Self {
+        let expected = KeyValue::new(key, value);
+        if !self.span.attributes.contains(&expected) {
+            fail(
+                format_args!(
+                    "span {:?} has no attribute {}",
+                    self.span.name,
+                    format_attributes([&expected])[0]
+                ),
+                &format_attributes([&expected])[0],
+                &format_attributes(&self.span.attributes),
+            );
+        }
+        self
+    }
+
+    /// Checks the attribute `key` is set, whatever its value.
+    #[track_caller]
+    pub fn has_attr_key(self, key: impl Into<Key>) -> Self {
+        let key = key.into();
+        if !self.span.attributes.iter().any(|kv| kv.key == key) {
+            fail(
+                format_args!("span {:?} has no attribute {}", self.span.name, key),
+                &key,
+                &format_attributes(&self.span.attributes),
+            );
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn has_event(self, name: &str) -> Self {
+        if !self.span.events.iter().any(|event| event.name == name) {
+            let names: Vec<String> = self.span.events.iter().map(|event| format!("{:?}", event.name)).collect();
+            fail(format_args!("span {:?} has no event {:?}", self.span.name, name), format!("{:?}", name), &names);
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn has_kind(self, kind: SpanKind) -> Self {
+        if self.span.span_kind != kind {
+            fail(
+                format_args!("span {:?} has the wrong kind", self.span.name),
+                format!("{:?}", kind),
+                &[format!("{:?}", self.span.span_kind)],
+            );
+        }
+        self
+    }
+
+    /// Checks the span status is an error, as set by [`crate::error_event!`].
+    #[track_caller]
+    pub fn is_error(self) -> Self {
+        if !matches!(self.span.status, Status::Error { .. }) {
+            fail(
+                format_args!("span {:?} is not an error", self.span.name),
+                "Error",
+                &[format!("{:?}", self.span.status)],
+            );
+        }
+        self
+    }
+}
+
+/// Fields a log record has to match in [`crate::assert_log!`].
+#[derive(Debug, Clone, Default)]
+pub struct LogMatcher {
+    severity: Option<Severity>,
+    body: Option<AnyValue>,
+    name: Option<String>,
+}
+
+impl LogMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<AnyValue>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Matches the event name passed to [`crate::log!`].
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    fn matches(&self, record: &SdkLogRecord) -> bool {
+        self.severity.is_none_or(|severity| record.severity_number() == Some(severity))
+            && self.body.as_ref().is_none_or(|body| record.body() == Some(body))
+            && self.name.as_deref().is_none_or(|name| record.event_name() == Some(name))
+    }
+
+    /// Returns the last matching log record.
+    #[track_caller]
+    pub fn assert(self, telemetry: &Telemetry) -> LogAssertion {
+        let records = telemetry.logs();
+        match records.iter().rev().find(|record| self.matches(record)) {
+            Some(record) => LogAssertion { record: record.clone() },
+            None => {
+                let found: Vec<String> = records.iter().map(|record| self.describe(record)).collect();
+                fail("no log record matched", self.describe_expected(), &found)
+            }
+        }
+    }
+
+    fn describe_expected(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(severity) = self.severity {
+            fields.push(format!("severity={}", severity.name()));
+        }
+        if let Some(body) = &self.body {
+            fields.push(format!("body={}", format_any_value(body)));
+        }
+        if let Some(name) = &self.name {
+            fields.push(format!("name={:?}", name));
+        }
+        fields.join(" ")
+    }
+
+    /// Describes `record` by the fields the matcher looks at.
+    fn describe(&self, record: &SdkLogRecord) -> String {
+        let severity = record.severity_number().map_or("none", |severity| severity.name());
+        let body = record.body().map_or("none".to_string(), format_any_value);
+        let name = record.event_name().map_or("none".to_string(), |name| format!("{:?}", name));
+        format!("severity={} body={} name={}", severity, body, name)
+    }
+}
+
+/// Assertions on an emitted log record.
+#[derive(Debug)]
+pub struct LogAssertion {
+    record: SdkLogRecord,
+}
+
+impl LogAssertion {
+    /// The log record under test.
+    pub fn record(&self) -> &SdkLogRecord {
+        &self.record
+    }
+
+    #[track_caller]
+    pub fn has_attr(self, key: impl Into<Key>, value: impl Into<AnyValue>) -> Self {
+        let (key, value) = (key.into(), value.into());
+        let found = self.record.attributes_iter().any(|(k, v)| *k == key && *v == value);
+        if !found {
+            let attributes: Vec<String> = self
+                .record
+                .attributes_iter()
+                .map(|(key, value)| format!("{}={}", key, format_any_value(value)))
+                .collect();
+            let expected = format!("{}={}", key, format_any_value(&value));
+            fail(format_args!("log record has no attribute {}", expected), &expected, &attributes);
+        }
+        self
+    }
+
+    /// Checks the record was emitted within the span named `name`.
+    #[track_caller]
+    pub fn in_span(self, telemetry: &Telemetry, name: &str) -> Self {
+        let span = self.record.trace_context().and_then(|context| {
+            telemetry
+                .spans()
+                .into_iter()
+                .find(|span| span.span_context.span_id() == context.span_id)
+        });
+        match span {
+            Some(span) if span.name == name => {}
+            Some(span) => fail("log record was emitted in another span", format!("{:?}", name), &[format!("{:?}", span.name)]),
+            None => fail("log record was not emitted in a finished span", format!("{:?}", name), &[]),
+        }
+        self
+    }
+}
+
+/// Assertions on the data points of a metric.
+#[derive(Debug)]
+pub struct MetricAssertion {
+    name: String,
+    points: Vec<MetricPoint>,
+}
+
+impl MetricAssertion {
+    /// Collects the metrics and finds the points of the metric `name`.
+    #[track_caller]
+    pub fn new(telemetry: &Telemetry, name: &str) -> Self {
+        let metrics = telemetry.metrics();
+        let points: Vec<MetricPoint> = metrics.iter().filter(|point| point.name == name).cloned().collect();
+        if points.is_empty() {
+            let mut names: Vec<String> = metrics.iter().map(|point| format!("{:?}", point.name)).collect();
+            names.dedup();
+            fail(format_args!("no metric named {:?} was recorded", name), format!("{:?}", name), &names);
+        }
+        Self {
+            name: name.to_string(),
+            points,
+        }
+    }
+
+    /// The data points under test.
+    pub fn points(&self) -> &[MetricPoint] {
+        &self.points
+    }
+
+    /// Narrows the assertion to the points with the attribute `key=value`.
+    #[track_caller]
+    pub fn with_attr(self, key: impl Into<Key>, value: impl Into<Value>) -> Self {
+        let expected = KeyValue::new(key, value);
+        let (points, others): (Vec<_>, Vec<_>) = self
+            .points
+            .into_iter()
+            .partition(|point| point.attributes.contains(&expected));
+        if points.is_empty() {
+            let found: Vec<String> = others.iter().map(|point| format_attributes(&point.attributes).join(" ")).collect();
+            fail(
+                format_args!("metric {:?} has no point with {}", self.name, format_attributes([&expected])[0]),
+                &format_attributes([&expected])[0],
+                &found,
+            );
+        }
+        Self { name: self.name, points }
+    }
+
+    /// Checks the total of the counter and up-down counter points.
+    #[track_caller]
+    pub fn sum(self, expected: impl Into<f64>) -> Self {
+        let total = self.total(&[MetricKind::Counter, MetricKind::UpDownCounter], |point| point.value);
+        self.check("sum", expected.into(), total)
+    }
+
+    /// Checks the value of the gauge points.
+    #[track_caller]
+    pub fn gauge(self, expected: impl Into<f64>) -> Self {
+        let total = self.total(&[MetricKind::Gauge], |point| point.value);
+        self.check("gauge", expected.into(), total)
+    }
+
+    /// Checks how many values the histogram points recorded.
+    #[track_caller]
+    pub fn count(self, expected: u64) -> Self {
+        let total = self.total(&[MetricKind::Histogram], |point| point.count.unwrap_or(0) as f64);
+        self.check("count", expected as f64, total)
+    }
+
+    /// Checks the sum of the values the histogram points recorded.
+    #[track_caller]
+    pub fn histogram_sum(self, expected: impl Into<f64>) -> Self {
+        let total = self.total(&[MetricKind::Histogram], |point| point.value);
+        self.check("histogram sum", expected.into(), total)
+    }
+
+    fn total(&self, kinds: &[MetricKind], value: impl Fn(&MetricPoint) -> f64) -> Option<f64> {
+        let points: Vec<&MetricPoint> = self.points.iter().filter(|point| kinds.contains(&point.kind)).collect();
+        (!points.is_empty()).then(|| points.into_iter().map(value).sum())
+    }
+
+    #[track_caller]
+    fn check(self, what: &str, expected: f64, actual: Option<f64>) -> Self {
+        match actual {
+            Some(actual) if actual == expected => self,
+            Some(actual) => fail(
+                format_args!("metric {:?} has the wrong {}", self.name, what),
+                expected,
+                &[actual.to_string()],
+            ),
+            None => {
+                let kinds: Vec<String> = self.points.iter().map(|point| format!("{:?}", point.kind)).collect();
+                fail(format_args!("metric {:?} has no {}", self.name, what), what, &kinds)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    use opentelemetry::logs::Severity;
+
+    #[track_caller]
+    fn panic_message(f: impl FnOnce()) -> String {
+        let payload = catch_unwind(AssertUnwindSafe(f)).expect_err("assertion passed");
+        payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn span_assertions() {
+        let _telemetry = crate::testing::scoped();
+        {
+            let _parent = crate::context!("assert parent").attach();
+            let _child = crate::context!("assert child", region = "eu", child_attr = 124).attach();
+            crate::event!("retried");
+        }
+
+        crate::assert_span!("assert child")
+            .has_parent("assert parent")
+            .has_attr("region", "eu")
+            .has_event("retried");
+        crate::assert_span!("assert parent").is_root();
+
+        let message = panic_message(|| {
+            crate::assert_span!("assert child").has_attr("child_attr", 123);
+        });
+        assert_eq!(
+            message,
+            "span \"assert child\" has no attribute child_attr=123\n  \
+             expected: child_attr=123\n  \
+             found:    region=\"eu\", child_attr=124"
+        );
+
+        let message = panic_message(|| {
+            crate::assert_span!("assert parent").has_parent("assert child");
+        });
+        assert!(message.starts_with("span \"assert parent\" has no parent\n"), "{}", message);
+    }
+
+    #[test]
+    fn log_assertions() {
+        let _telemetry = crate::testing::scoped();
+        crate::log!(Some("assert log"), Severity::Error, "assert error!", test_key = "hello");
+
+        crate::assert_log!(severity = Error, body = "assert error!").has_attr("test_key", "hello");
+        crate::assert_log!(name = "assert log", severity = Severity::Error);
+
+        let message = panic_message(|| {
+            crate::assert_log!(severity = Warn, body = "assert error!");
+        });
+        assert!(
+            message.starts_with("no log record matched\n  expected: severity=WARN body=\"assert error!\"\n"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("severity=ERROR body=\"assert error!\" name=\"assert log\""),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn metric_assertions() {
+        let _telemetry = crate::testing::scoped();
+        let meter = crate::init::meter();
+        let counter = meter.u64_counter("assert.requests").build();
+        counter.add(1, &[crate::KeyValue::new("route", "/")]);
+        counter.add(2, &[crate::KeyValue::new("route", "/health")]);
+        meter.f64_histogram("assert.latency").build().record(1.5, &[]);
+
+        crate::assert_metric!("assert.requests").sum(3).with_attr("route", "/").sum(1);
+        crate::assert_metric!("assert.latency").count(1).histogram_sum(1.5);
+
+        let message = panic_message(|| {
+            crate::assert_metric!("assert.requests").sum(4);
+        });
+        assert_eq!(
+            message,
+            "metric \"assert.requests\" has the wrong sum\n  expected: 4\n  found:    3"
+        );
+    }
+}
@@ -1,30 +1,89 @@
+use opentelemetry::trace::noop::NoopTracer;
 use opentelemetry::trace::{TraceContextExt, Tracer};
 use opentelemetry_sdk as sdk;
 
-pub(crate) fn init_tracing() -> sdk::trace::SdkTracerProvider {
-    let mut builder = sdk::trace::TracerProviderBuilder::default();
+use crate::clock::ClockSpanProcessor;
+use crate::config::{BatchConfig, Config, Exporter};
+use crate::error::OtexError;
+use crate::sampling::TailSamplingProcessor;
+
+pub(crate) fn init_tracing(
+    config: &Config,
+    resource: &sdk::Resource,
+) -> Result<Option<sdk::trace::SdkTracerProvider>, OtexError> {
+    let signal = &config.traces;
+    if !signal.enabled {
+        return Ok(None);
+    }
 
-    let export_enabled: bool = std::env::var("OTEX_EXPORT")
-        .map(|s| s.to_lowercase())
-        .unwrap_or("true".to_string())
-        .parse()
-        .unwrap_or(true);
+    let mut builder = sdk::trace::TracerProviderBuilder::default()
+        .with_resource(resource.clone())
+        .with_sampler(crate::sampling::build_sampler(config))
+        .with_id_generator(crate::id::Ids::new(config.id_generator));
 
-    if export_enabled {
-        let exporter = opentelemetry_otlp::SpanExporterBuilder::default()
-            .with_http()
-            .build()
-            .expect("failed to build exporter");
+    match signal.exporter {
+        Exporter::Otlp => {
+            let exporter = crate::otlp::span_exporter(config)?;
+            builder = with_exporter(builder, config, exporter);
+        }
+        Exporter::Console => {
+            let console_exporter = crate::console::ConsoleSpanExporter::new();
+            builder = with_exporter(builder, config, console_exporter);
+        }
+        #[cfg(feature = "stdout")]
+        Exporter::Stdout => {
+            let stdout_exporter = opentelemetry_stdout::SpanExporter::default();
+            builder = with_exporter(builder, config, stdout_exporter);
+        }
+        #[cfg(feature = "file")]
+        Exporter::File => {
+            let file_exporter = crate::file::FileSpanExporter::new(crate::file::sink(config)?);
+            builder = with_exporter(builder, config, file_exporter);
+        }
+        Exporter::None => {}
+    }
 
-        builder = builder.with_batch_exporter(exporter);
+    Ok(Some(builder.build()))
+}
+
+/// Batches spans for `exporter`, behind the tail sampling processor if configured.
+fn with_exporter(
+    builder: sdk::trace::TracerProviderBuilder,
+    config: &Config,
+    exporter: impl sdk::trace::SpanExporter + 'static,
+) -> sdk::trace::TracerProviderBuilder {
+    let batch = sdk::trace::BatchSpanProcessor::builder(exporter)
+        .with_batch_config(batch_config(&config.traces.batch))
+        .build();
+    match &config.tail_sampling {
+        Some(policy) => with_processor(builder, config, TailSamplingProcessor::new(policy.clone(), batch)),
+        None => with_processor(builder, config, batch),
     }
+}
 
-    #[cfg(feature = "stdout")]
-    {
-        let stdout_exporter = opentelemetry_stdout::SpanExporter::default();
-        builder = builder.with_batch_exporter(stdout_exporter);
+/// Adds `processor`, taking span times from the configured clock if any.
+pub(crate) fn with_processor(
+    builder: sdk::trace::TracerProviderBuilder,
+    config: &Config,
+    processor: impl sdk::trace::SpanProcessor + 'static,
+) -> sdk::trace::TracerProviderBuilder {
+    match &config.clock {
+        Some(clock) => builder.with_span_processor(ClockSpanProcessor::new(clock.clone(), processor)),
+        None => builder.with_span_processor(processor),
     }
+}
 
+fn batch_config(batch: &BatchConfig) -> sdk::trace::BatchConfig {
+    let mut builder = sdk::trace::BatchConfigBuilder::default();
+    if let Some(size) = batch.max_queue_size {
+        builder = builder.with_max_queue_size(size);
+    }
+    if let Some(size) = batch.max_export_batch_size {
+        builder = builder.with_max_export_batch_size(size);
+    }
+    if let Some(delay) = batch.scheduled_delay {
+        builder = builder.with_scheduled_delay(delay);
+    }
     builder.build()
 }
 
@@ -34,14 +93,7 @@ pub fn new_span(
     kind: opentelemetry::trace::SpanKind,
     attributes: &[opentelemetry::KeyValue],
 ) -> opentelemetry::Context {
-    let tracer = crate::init::tracer();
-    let span_builder = tracer
-        .span_builder(name.to_string())
-        .with_kind(kind)
-        .with_attributes(attributes.to_owned());
-
-    let span = tracer.build(span_builder);
-    opentelemetry::Context::current_with_span(span)
+    new_span_with_parent(name, kind, attributes, opentelemetry::Context::current())
 }
 
 /// Creates a new span with an explicit parent context.
@@ -71,28 +123,35 @@ pub fn new_span_with_parent(
     attributes: &[opentelemetry::KeyValue],
     parent_context: opentelemetry::Context,
 ) -> opentelemetry::Context {
-    let tracer = crate::init::tracer();
-    let span_builder = tracer
-        .span_builder(name.to_string())
+    let span_builder = opentelemetry::trace::SpanBuilder::from_name(name.to_string())
         .with_kind(kind)
         .with_attributes(attributes.to_owned());
 
-    let span = tracer.build_with_context(span_builder, &parent_context);
-    parent_context.with_span(span)
+    match crate::init::enabled_tracer() {
+        Some(tracer) => {
+            let span = tracer.build_with_context(span_builder, &parent_context);
+            parent_context.with_span(span)
+        }
+        // With traces disabled the parent's span context is still propagated.
+        None => {
+            let span = NoopTracer::new().build_with_context(span_builder, &parent_context);
+            parent_context.with_span(span)
+        }
+    }
 }
 
 pub fn new_event(name: &str, attributes: &[opentelemetry::KeyValue]) {
     let context = opentelemetry::Context::current();
     context
         .span()
-        .add_event(name.to_string(), attributes.to_vec());
+        .add_event_with_timestamp(name.to_string(), crate::init::now(), attributes.to_vec());
 }
 
 pub fn new_error_event(name: &str, description: &str, attributes: &[opentelemetry::KeyValue]) {
     let context = opentelemetry::Context::current();
     context
         .span()
-        .add_event(name.to_string(), attributes.to_vec());
+        .add_event_with_timestamp(name.to_string(), crate::init::now(), attributes.to_vec());
 
     context.span().set_status(opentelemetry::trace::Status::error(description.to_string()));
 }
@@ -100,26 +159,33 @@ pub fn new_error_event(name: &str, description: &str, attributes: &[opentelemetr
 #[cfg(test)]
 mod test {
 
-    use crate::{FutureExt, event, context};
+    use crate::{FutureExt, KeyValue, event, context};
 
     #[test]
     fn span_macro() {
-        let otex = crate::init();
+        let _telemetry = crate::testing::scoped();
         {
-            let parent = context!("hello", crate::trace::SpanKind::Internal, test_attr = "value");
+            let _parent = context!("hello", crate::trace::SpanKind::Internal, test_attr = "value").attach();
             event!("parent event");
 
             let child_attr = 123;
-            let mut child = context!("world", crate::trace::SpanKind::Internal, child_attr);
+            let _child = context!("world", crate::trace::SpanKind::Internal, child_attr).attach();
 
             event!("child event");
         }
-        otex.shutdown();
+
+        crate::assert_span!("world")
+            .has_parent("hello")
+            .has_attr("child_attr", 123)
+            .has_event("child event");
+        crate::assert_span!("hello")
+            .has_attr("test_attr", "value")
+            .has_event("parent event");
     }
 
     #[tokio::test]
     async fn async_span() {
-        let otex = crate::init();
+        let telemetry = crate::testing::scoped();
         {
             let _parent = context!("hello", crate::trace::SpanKind::Internal, test_attr = "value").attach();
             event!("parent event");
@@ -134,6 +200,14 @@ mod test {
 
             tokio::spawn(task).await.unwrap();
         }
-        otex.shutdown();
+
+        let spans = telemetry.spans();
+        let event = spans
+            .iter()
+            .flat_map(|span| span.events.iter().map(move |event| (span, event)))
+            .find(|(_, event)| event.attributes.contains(&KeyValue::new("child_name", "childevent")));
+        let (world, _) = event.expect("child event recorded on the world span");
+        assert_eq!(world.name, "world");
+        assert_eq!(telemetry.parent(world).unwrap().name, "hello");
     }
 }
@@ -0,0 +1,349 @@
+//! A `tracing-subscriber` layer recording `tracing` spans and events through
+//! otex, so they land in the same traces as the otex macros.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, Severity};
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, ContextGuard, Key, KeyValue, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{self, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+thread_local! {
+    /// Contexts attached while a `tracing` span is entered on this thread.
+    static ENTERED: RefCell<Vec<(Id, ContextGuard)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Turns `tracing` spans into OpenTelemetry spans created with otex's tracer,
+/// and `tracing` events into events on the current span or, outside of any
+/// span, into log records.
+///
+/// Spans can be renamed with an `otel.name` field and given a kind with
+/// `otel.kind` (`server`, `client`, `producer`, `consumer`, `internal`).
+///
+/// ```ignore
+/// use tracing_subscriber::prelude::*;
+///
+/// let _otex = otex::init(config);
+/// tracing_subscriber::registry().with(otex::OtexLayer::new()).init();
+/// ```
+#[derive(Debug, Clone)]
+pub struct OtexLayer {
+    span_events: bool,
+}
+
+impl Default for OtexLayer {
+    fn default() -> Self {
+        Self { span_events: true }
+    }
+}
+
+impl OtexLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether events inside a span become span events, the default, or log records.
+    pub fn with_span_events(mut self, span_events: bool) -> Self {
+        self.span_events = span_events;
+        self
+    }
+}
+
+/// The OpenTelemetry context of a `tracing` span, kept in its extensions.
+struct OtelContext(Context);
+
+impl<S> Layer<S> for OtexLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: layer::Context<'_, S>) {
+        let Some(tracer) = crate::init::try_tracer() else {
+            return;
+        };
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let parent = if let Some(parent) = attrs.parent() {
+            ctx.span(parent)
+                .and_then(|parent| parent.extensions().get::<OtelContext>().map(|cx| cx.0.clone()))
+                .unwrap_or_default()
+        } else if attrs.is_contextual() {
+            // Entered `tracing` spans are attached, so this also covers them.
+            Context::current()
+        } else {
+            Context::new()
+        };
+
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let metadata = attrs.metadata();
+        let name = fields.name.take().unwrap_or_else(|| metadata.name().to_string());
+        let mut attributes = fields.attributes;
+        attributes.extend(code_attributes(metadata));
+
+        let builder = tracer
+            .span_builder(name)
+            .with_kind(fields.kind.unwrap_or(SpanKind::Internal))
+            .with_attributes(attributes);
+        let otel_span = tracer.build_with_context(builder, &parent);
+        span.extensions_mut().insert(OtelContext(parent.with_span(otel_span)));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(OtelContext(cx)) = extensions.get::<OtelContext>() else {
+            return;
+        };
+
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        let otel_span = cx.span();
+        if let Some(name) = fields.name {
+            otel_span.update_name(name);
+        }
+        otel_span.set_attributes(fields.attributes);
+    }
+
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: layer::Context<'_, S>) {
+        let (Some(span), Some(follows)) = (ctx.span(id), ctx.span(follows)) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let follows = follows.extensions();
+        if let (Some(OtelContext(cx)), Some(OtelContext(follows))) =
+            (extensions.get::<OtelContext>(), follows.get::<OtelContext>())
+        {
+            cx.span().add_link(follows.span().span_context().clone(), Vec::new());
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+
+        let span_context = ctx.event_span(event).and_then(|span| {
+            let extensions = span.extensions();
+            extensions.get::<OtelContext>().map(|cx| cx.0.clone())
+        });
+
+        match span_context {
+            Some(cx) if self.span_events => {
+                let name = fields.message.unwrap_or_else(|| metadata.name().to_string());
+                let otel_span = cx.span();
+                if *metadata.level() == Level::ERROR {
+                    otel_span.set_status(Status::error(name.clone()));
+                }
+                otel_span.add_event_with_timestamp(name, crate::init::now(), fields.attributes);
+            }
+            span_context => {
+                let Some(logger) = crate::init::try_logger() else {
+                    return;
+                };
+                let mut record = logger.create_log_record();
+                let severity = severity(metadata.level());
+                record.set_severity_number(severity);
+                record.set_severity_text(severity.name());
+                record.set_target(metadata.target().to_string());
+                if let Some(message) = fields.message {
+                    record.set_body(message.into());
+                }
+                let attributes = fields.attributes.into_iter().chain(code_attributes(metadata));
+                record.add_attributes(attributes.map(|kv| (kv.key, any_value(kv.value))));
+
+                // The logger takes the trace context from the event's span.
+                let _guard = span_context.map(Context::attach);
+                logger.emit(record);
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(OtelContext(cx)) = extensions.get::<OtelContext>() {
+            let guard = cx.clone().attach();
+            ENTERED.with(|entered| entered.borrow_mut().push((id.clone(), guard)));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: layer::Context<'_, S>) {
+        let guard = ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            let index = entered.iter().rposition(|(entered, _)| entered == id)?;
+            Some(entered.remove(index))
+        });
+        drop(guard);
+    }
+
+    fn on_close(&self, id: Id, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        if let Some(OtelContext(cx)) = span.extensions_mut().remove::<OtelContext>() {
+            cx.span().end();
+        }
+    }
+}
+
+fn code_attributes(metadata: &Metadata<'_>) -> Vec<KeyValue> {
+    let mut attributes = Vec::new();
+    if let Some(module_path) = metadata.module_path() {
+        attributes.push(KeyValue::new("code.namespace", module_path.to_string()));
+    }
+    if let Some(file) = metadata.file() {
+        attributes.push(KeyValue::new("code.filepath", file.to_string()));
+    }
+    if let Some(line) = metadata.line() {
+        attributes.push(KeyValue::new("code.lineno", i64::from(line)));
+    }
+    attributes
+}
+
+fn severity(level: &Level) -> Severity {
+    match *level {
+        Level::ERROR => Severity::Error,
+        Level::WARN => Severity::Warn,
+        Level::INFO => Severity::Info,
+        Level::DEBUG => Severity::Debug,
+        Level::TRACE => Severity::Trace,
+    }
+}
+
+fn any_value(value: Value) -> AnyValue {
+    match value {
+        Value::Bool(b) => AnyValue::Boolean(b),
+        Value::I64(i) => AnyValue::Int(i),
+        Value::F64(f) => AnyValue::Double(f),
+        Value::String(s) => AnyValue::String(s),
+        other => AnyValue::from(other.to_string()),
+    }
+}
+
+fn span_kind(kind: &str) -> Option<SpanKind> {
+    match kind.to_lowercase().as_str() {
+        "server" => Some(SpanKind::Server),
+        "client" => Some(SpanKind::Client),
+        "producer" => Some(SpanKind::Producer),
+        "consumer" => Some(SpanKind::Consumer),
+        "internal" => Some(SpanKind::Internal),
+        _ => None,
+    }
+}
+
+/// Collects the fields of a span or event.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    name: Option<String>,
+    kind: Option<SpanKind>,
+    attributes: Vec<KeyValue>,
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.record_i64(field, value),
+            Err(_) => self.record_str(field, &value.to_string()),
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = Some(value.to_string()),
+            "otel.name" => self.name = Some(value.to_string()),
+            "otel.kind" => self.kind = span_kind(value),
+            name => self.attributes.push(KeyValue::new(Key::from_static_str(name), value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::KeyValue;
+    use opentelemetry::logs::Severity;
+    use opentelemetry::trace::SpanKind;
+    use tracing_subscriber::prelude::*;
+
+    use super::OtexLayer;
+
+    fn with_layer(layer: OtexLayer, f: impl FnOnce()) {
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, f);
+    }
+
+    #[test]
+    fn spans_and_events() {
+        let telemetry = crate::testing::scoped();
+        with_layer(OtexLayer::new(), || {
+            let request = tracing::info_span!("request", otel.kind = "server", route = "/orders");
+            let _request = request.enter();
+            {
+                let _query = tracing::debug_span!("query", rows = tracing::field::Empty).entered();
+                tracing::Span::current().record("rows", 3);
+                tracing::info!(cached = false, "query done");
+                // otex spans nest under the entered `tracing` span.
+                drop(crate::context!("otex child"));
+            }
+            tracing::error!("failed");
+        });
+
+        crate::assert_span!("request")
+            .is_root()
+            .has_kind(SpanKind::Server)
+            .has_attr("route", "/orders")
+            .has_event("failed")
+            .is_error();
+        crate::assert_span!("query")
+            .has_parent("request")
+            .has_attr("rows", 3)
+            .has_event("query done");
+        crate::assert_span!("otex child").has_parent("query");
+        let query = telemetry.span("query").unwrap();
+        assert_eq!(query.events[0].attributes, [KeyValue::new("cached", false)]);
+        assert!(telemetry.logs().is_empty());
+    }
+
+    #[test]
+    fn events_as_logs() {
+        let telemetry = crate::testing::scoped();
+        with_layer(OtexLayer::new().with_span_events(false), || {
+            tracing::warn!(target: "billing", attempt = 2, "retrying");
+            let _span = tracing::info_span!("charge").entered();
+            tracing::info!("charged");
+        });
+
+        crate::assert_log!(severity = Warn, body = "retrying").has_attr("attempt", 2);
+        crate::assert_log!(severity = Info, body = "charged").in_span(&telemetry, "charge");
+        let retrying = &telemetry.logs_with_severity(Severity::Warn)[0];
+        assert_eq!(retrying.target().map(|target| target.as_ref()), Some("billing"));
+        assert!(retrying.trace_context().is_none());
+    }
+}